@@ -0,0 +1,266 @@
+//! Checksums of the logical contents of datasets, independent of chunking and compression
+//!
+//! Each dataset is hashed with its type and shape, followed by its elements in row-major order
+//! as little-endian bytes, so a dataset that was copied or repacked with other filters has the
+//! same digest. The digest of the file hashes the paths and digests of all datasets in order.
+//!
+//! Elements of other fixed-size types, e.g. compounds or fixed-length strings, are hashed as the
+//! bytes of their native memory type, which depend on the byte order of the machine. Strings of
+//! variable length are hashed as their length followed by their bytes, other types of variable
+//! length are not supported.
+
+use std::io::Write;
+
+use anyhow::{bail, Context};
+use hdf5::{
+    h5call,
+    types::{TypeDescriptor, VarLenAscii, VarLenUnicode},
+    Dataset, H5Type,
+};
+use hdf5_sys::{
+    h5::hsize_t,
+    h5d::{H5Dget_space, H5Dget_type, H5Dread},
+    h5i::hid_t,
+    h5p::H5P_DEFAULT,
+    h5s::{H5S_seloper_t, H5Sclose, H5Screate_simple, H5Sselect_hyperslab, H5S_ALL},
+    h5t::{H5T_direction_t, H5Tclose, H5Tget_native_type, H5Tget_size},
+};
+use num_traits::ToBytes;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{ChecksumArgs, Config},
+    my_hdf5::{
+        chunked::RowBlocks,
+        inspect::GroupInfo,
+        util::{type_name, with_native_type, NativePrimitiveType},
+    },
+};
+
+pub fn handle_checksum_cmd(args: &ChecksumArgs, _cfg: &Config) -> anyhow::Result<()> {
+    log::trace!("{args:?}");
+    log::debug!("opening: {:?}", args.src_hdf5.as_path());
+    let file = hdf5::File::open(args.src_hdf5.as_path())?;
+
+    let mut paths: Vec<String> = GroupInfo::read(&file)?
+        .datasets()
+        .into_iter()
+        .map(|dataset| dataset.path.clone())
+        .collect();
+    paths.sort();
+
+    let mut stdout = std::io::stdout().lock();
+    let mut file_hasher = Sha256::new();
+    for path in paths {
+        let dataset = file.dataset(&path)?;
+        let digest = dataset_digest(&dataset, args.memory_budget << 20)
+            .with_context(|| format!("Failed hashing {path}"))?;
+        writeln!(stdout, "{}  {path}", to_hex(&digest))?;
+        file_hasher.update(path.as_bytes());
+        file_hasher.update([0]);
+        file_hasher.update(digest);
+    }
+    writeln!(
+        stdout,
+        "{}  {}",
+        to_hex(&file_hasher.finalize()),
+        args.src_hdf5.display()
+    )?;
+    Ok(())
+}
+
+/// Digest of the type, shape and elements of a dataset
+///
+/// At most `memory_budget` bytes are used for reading the dataset and its bytes to hash, apart
+/// from the contents of strings of variable length.
+pub fn dataset_digest(dataset: &Dataset, memory_budget: usize) -> anyhow::Result<Vec<u8>> {
+    let dtype = dataset.dtype()?;
+    let mut hasher = Sha256::new();
+    hasher.update(type_name(&dtype).as_bytes());
+    hasher.update([0]);
+    let shape = dataset.shape();
+    hasher.update((shape.len() as u64).to_le_bytes());
+    for len in &shape {
+        hasher.update((*len as u64).to_le_bytes());
+    }
+
+    let Some(native) = NativePrimitiveType::try_from_dtype(&dtype) else {
+        match dtype.to_descriptor()? {
+            TypeDescriptor::VarLenUnicode => {
+                hash_strings::<VarLenUnicode>(dataset, memory_budget, &mut hasher, |s| s.as_str())?
+            }
+            TypeDescriptor::VarLenAscii => {
+                hash_strings::<VarLenAscii>(dataset, memory_budget, &mut hasher, |s| s.as_str())?
+            }
+            descriptor if has_variable_length(&descriptor) => {
+                bail!("Elements of the variable-length type {descriptor} are not supported")
+            }
+            _ => hash_native_bytes(dataset, memory_budget, &mut hasher)?,
+        }
+        return Ok(hasher.finalize().to_vec());
+    };
+    with_native_type!(
+        native,
+        T,
+        hash_elements::<T>(dataset, memory_budget, &mut hasher)?
+    );
+    Ok(hasher.finalize().to_vec())
+}
+
+fn hash_elements<T>(
+    dataset: &Dataset,
+    memory_budget: usize,
+    hasher: &mut Sha256,
+) -> anyhow::Result<()>
+where
+    T: H5Type + Copy + ToBytes,
+{
+    if dataset.ndim() == 0 {
+        hasher.update(dataset.read_scalar::<T>()?.to_le_bytes());
+        return Ok(());
+    }
+    let mut bytes = vec![];
+    for block in RowBlocks::<T>::new(dataset, memory_budget / 2) {
+        let (_, block) = block?;
+        bytes.clear();
+        // Blocks are read in the standard, row-major layout
+        for x in block.iter() {
+            bytes.extend_from_slice(x.to_le_bytes().as_ref());
+        }
+        hasher.update(&bytes);
+    }
+    Ok(())
+}
+
+fn hash_strings<T: H5Type>(
+    dataset: &Dataset,
+    memory_budget: usize,
+    hasher: &mut Sha256,
+    as_str: impl Fn(&T) -> &str,
+) -> anyhow::Result<()> {
+    let mut hash = |s: &T| {
+        let bytes = as_str(s).as_bytes();
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    if dataset.ndim() == 0 {
+        hash(&dataset.read_scalar::<T>()?);
+        return Ok(());
+    }
+    for block in RowBlocks::<T>::new(dataset, memory_budget) {
+        let (_, block) = block?;
+        block.iter().for_each(&mut hash);
+    }
+    Ok(())
+}
+
+/// Whether elements of the type, or of any of its members, have a variable length
+fn has_variable_length(descriptor: &TypeDescriptor) -> bool {
+    match descriptor {
+        TypeDescriptor::VarLenArray(_)
+        | TypeDescriptor::VarLenAscii
+        | TypeDescriptor::VarLenUnicode => true,
+        TypeDescriptor::FixedArray(element, _) => has_variable_length(element),
+        TypeDescriptor::Compound(compound) => compound
+            .fields
+            .iter()
+            .any(|field| has_variable_length(&field.ty)),
+        _ => false,
+    }
+}
+
+/// Hash the elements of a fixed-size type as the bytes of the native memory type the library
+/// converts them to, reading blocks of whole rows of at most `memory_budget` bytes
+fn hash_native_bytes(
+    dataset: &Dataset,
+    memory_budget: usize,
+    hasher: &mut Sha256,
+) -> anyhow::Result<()> {
+    let file_type = h5call!(H5Dget_type(dataset.id()))?;
+    let mem_type = h5call!(H5Tget_native_type(
+        file_type,
+        H5T_direction_t::H5T_DIR_ASCEND
+    ));
+    h5call!(H5Tclose(file_type))?;
+    let mem_type = mem_type?;
+    let file_space = h5call!(H5Dget_space(dataset.id()));
+    let hashed = file_space
+        .map_err(anyhow::Error::from)
+        .and_then(|file_space| {
+            let hashed = hash_rows(dataset, mem_type, file_space, memory_budget, hasher);
+            h5call!(H5Sclose(file_space))?;
+            hashed
+        });
+    h5call!(H5Tclose(mem_type))?;
+    hashed
+}
+
+fn hash_rows(
+    dataset: &Dataset,
+    mem_type: hid_t,
+    file_space: hid_t,
+    memory_budget: usize,
+    hasher: &mut Sha256,
+) -> anyhow::Result<()> {
+    let elem_size = unsafe { H5Tget_size(mem_type) };
+    if elem_size == 0 {
+        bail!("Failed reading the size of the type");
+    }
+    let shape = dataset.shape();
+    if shape.is_empty() {
+        let mut buf = vec![0u8; elem_size];
+        h5call!(H5Dread(
+            dataset.id(),
+            mem_type,
+            H5S_ALL,
+            H5S_ALL,
+            H5P_DEFAULT,
+            buf.as_mut_ptr().cast()
+        ))?;
+        hasher.update(&buf);
+        return Ok(());
+    }
+
+    let rows = shape[0];
+    let row_bytes = shape[1..].iter().product::<usize>() * elem_size;
+    let rows_per_block = (memory_budget / row_bytes.max(1)).max(1);
+    let mut buf = vec![];
+    for start in (0..rows).step_by(rows_per_block) {
+        let mut offset: Vec<hsize_t> = vec![0; shape.len()];
+        offset[0] = start as hsize_t;
+        let mut count: Vec<hsize_t> = shape.iter().map(|&len| len as hsize_t).collect();
+        count[0] = rows_per_block.min(rows - start) as hsize_t;
+        h5call!(H5Sselect_hyperslab(
+            file_space,
+            H5S_seloper_t::H5S_SELECT_SET,
+            offset.as_ptr(),
+            std::ptr::null(),
+            count.as_ptr(),
+            std::ptr::null()
+        ))?;
+        let mem_space = h5call!(H5Screate_simple(
+            shape.len() as i32,
+            count.as_ptr(),
+            std::ptr::null()
+        ))?;
+        // Padding between the members of compounds stays zero
+        buf.clear();
+        buf.resize(count[0] as usize * row_bytes, 0u8);
+        let read = h5call!(H5Dread(
+            dataset.id(),
+            mem_type,
+            mem_space,
+            file_space,
+            H5P_DEFAULT,
+            buf.as_mut_ptr().cast()
+        ));
+        h5call!(H5Sclose(mem_space))?;
+        read?;
+        hasher.update(&buf);
+    }
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -1,5 +1,15 @@
 use {
+    crate::{
+        expr::Expression,
+        filter::Filter,
+        find::{parse_attr_match, AttrMatch},
+        my_hdf5::util::NativePrimitiveType,
+        resample::{parse_period, parse_timestamps_pair},
+    },
     clap::{command, ArgAction, Args, Parser, Subcommand},
+    misc::{
+        EventsFormat, InspectFormat, Interpolation, NanPolicy, StatsFormat, TimeUnit, Transform,
+    },
     std::path::PathBuf,
     stderrlog::LogLevelNum,
 };
@@ -91,7 +101,13 @@ impl Config {
 #[derive(Debug, Subcommand)]
 pub enum Command {
     Plot(PlotArgs),
+    Stats(StatsArgs),
+    Events(EventsArgs),
+    Correlate(CorrelateArgs),
     Inspect(InspectArgs),
+    Find(FindArgs),
+    Diff(DiffArgs),
+    Checksum(ChecksumArgs),
     TestSwMr,
 }
 
@@ -102,15 +118,340 @@ pub struct PlotArgs {
     #[arg(required(true))]
     pub src_hdf5: PathBuf,
 
-    #[arg(short, long)]
-    pub dataset_name: String,
+    #[arg(short, long, required_unless_present("exprs"))]
+    pub dataset_name: Option<String>,
+
+    /// Plot a signal derived from datasets, e.g. `diff = (/adc/ch1 - /adc/ch0) * 0.5`,
+    /// columns of 2-D datasets are referenced as e.g. `/imu/acc[2]`. A `/` right after a path is
+    /// part of the path, divide with a space before it, e.g. `/adc/ch1 / 2`
+    #[arg(short, long = "expr", value_name("EXPR"))]
+    pub exprs: Vec<Expression>,
 
     #[arg(short, long, default_value("0"))]
     pub axis: usize,
 
+    /// Dataset with the position of each sample on the x-axis, e.g. timestamps, which the
+    /// series are plotted against and the derivative and integral transforms use
+    #[arg(short, long, value_name("DATASET"))]
+    pub x_axis: Option<String>,
+
+    /// Transform the samples before plotting
+    #[arg(short, long, value_name("TRANSFORM"))]
+    pub transform: Option<Transform>,
+
     /// Include every N'th sample in the plot
     #[arg(short, long, default_value("1"), value_name("N"))]
     pub subsample: usize,
+
+    /// Upper bound on the memory used for reading the dataset
+    #[arg(long, default_value("64"), value_name("MiB"))]
+    pub memory_budget: usize,
+
+    /// Overlay the rolling mean, ±σ band and min/max envelope over a window of N plotted samples
+    #[arg(long, value_name("N"))]
+    pub rolling: Option<usize>,
+
+    /// Filter the plotted series, one of `lowpass:<f>`, `highpass:<f>`, `bandpass:<f1>-<f2>`
+    /// and `notch:<f>` with frequencies like `10Hz` or `1.5kHz`, or `moving-average:<N>`.
+    /// Pass many times to apply several filters in order
+    #[arg(long = "filter", value_name("FILTER"))]
+    pub filters: Vec<Filter>,
+
+    /// Sample rate of the datasets, required by filters with frequencies unless the series
+    /// are resampled
+    #[arg(long, value_name("Hz"))]
+    pub sample_rate: Option<f64>,
+
+    /// Order of the Butterworth lowpass, highpass and bandpass filters
+    #[arg(long, default_value("4"), value_name("N"))]
+    pub filter_order: usize,
+
+    /// Plot the unfiltered series along with the filtered series
+    #[arg(long, requires("filters"))]
+    pub show_raw: bool,
+
+    /// Highlight the excursions of the first plotted series beyond the thresholds
+    #[command(flatten)]
+    pub highlight: Thresholds,
+
+    #[command(flatten)]
+    pub resampling: Resampling,
+
+    /// How NaN and infinite values are treated in the statistics
+    #[arg(long, default_value_t = NanPolicy::Skip, value_name("POLICY"))]
+    pub nan_policy: NanPolicy,
+
+    /// Use the stored values as they are, instead of unpacking datasets with CF attributes
+    /// (`scale_factor`, `add_offset`) and masking `_FillValue`, `missing_value` and values
+    /// outside of `valid_range` as NaN
+    #[arg(long)]
+    pub raw: bool,
+}
+
+#[derive(Debug, Args, Clone)]
+#[command(flatten_help = true)]
+pub struct StatsArgs {
+    /// Hdf5 file
+    #[arg(required(true))]
+    pub src_hdf5: PathBuf,
+
+    /// Dataset(s) to compute statistics for
+    #[arg(short, long = "dataset", required_unless_present("exprs"), num_args(1..))]
+    pub datasets: Vec<String>,
+
+    /// Compute statistics of a signal derived from datasets, e.g. `diff = (/adc/ch1 - /adc/ch0) * 0.5`,
+    /// columns of 2-D datasets are referenced as e.g. `/imu/acc[2]`. A `/` right after a path is
+    /// part of the path, divide with a space before it, e.g. `/adc/ch1 / 2`
+    #[arg(short, long = "expr", value_name("EXPR"))]
+    pub exprs: Vec<Expression>,
+
+    #[arg(short, long, default_value("0"))]
+    pub axis: usize,
+
+    /// Dataset with the position of each sample on the x-axis, e.g. timestamps, used by the
+    /// derivative and integral transforms
+    #[arg(short, long, value_name("DATASET"))]
+    pub x_axis: Option<String>,
+
+    /// Transform the samples before computing statistics
+    #[arg(short, long, value_name("TRANSFORM"), conflicts_with("lanes"))]
+    pub transform: Option<Transform>,
+
+    /// Report the statistics of each lane along AXIS of a 2-D dataset in a table,
+    /// e.g. `--lanes 0` for the statistics of each column, instead of folding the dataset
+    #[arg(
+        long,
+        value_name("AXIS"),
+        conflicts_with_all(["axis", "resample", "x_axis", "exprs"])
+    )]
+    pub lanes: Option<usize>,
+
+    /// Percentiles to report, in the range 0-100
+    #[arg(
+        short,
+        long,
+        value_delimiter(','),
+        default_values_t = [1.0, 5.0, 95.0, 99.0]
+    )]
+    pub percentiles: Vec<f64>,
+
+    /// Upper bound on the memory used for reading and computing statistics
+    #[arg(long, default_value("64"), value_name("MiB"))]
+    pub memory_budget: usize,
+
+    /// How NaN and infinite values are treated in the statistics
+    #[arg(long, default_value_t = NanPolicy::Skip, value_name("POLICY"))]
+    pub nan_policy: NanPolicy,
+
+    /// Use the stored values as they are, instead of unpacking datasets with CF attributes
+    /// (`scale_factor`, `add_offset`) and masking `_FillValue`, `missing_value` and values
+    /// outside of `valid_range` as NaN
+    #[arg(long)]
+    pub raw: bool,
+
+    /// Output format, `json` and `csv` are written with a stable schema for other tools
+    #[arg(short, long, default_value_t = StatsFormat::Text)]
+    pub format: StatsFormat,
+
+    /// Resample the datasets and the datasets of the expressions onto a common time grid
+    #[command(flatten)]
+    pub resampling: Resampling,
+}
+
+#[derive(Debug, Args, Clone)]
+#[command(flatten_help = true)]
+pub struct EventsArgs {
+    /// Hdf5 file
+    #[arg(required(true))]
+    pub src_hdf5: PathBuf,
+
+    /// Dataset to scan for excursions
+    #[arg(short, long)]
+    pub dataset: String,
+
+    #[arg(short, long, default_value("0"))]
+    pub axis: usize,
+
+    /// Dataset with the position of each sample on the x-axis, e.g. timestamps, to report
+    /// the start, end and duration of the events in
+    #[arg(short, long, value_name("DATASET"))]
+    pub x_axis: Option<String>,
+
+    #[command(flatten)]
+    pub thresholds: Thresholds,
+
+    /// Upper bound on the memory used for reading the dataset
+    #[arg(long, default_value("64"), value_name("MiB"))]
+    pub memory_budget: usize,
+
+    /// Use the stored values as they are, instead of unpacking datasets with CF attributes
+    #[arg(long)]
+    pub raw: bool,
+
+    #[arg(short, long, default_value_t = EventsFormat::Table)]
+    pub format: EventsFormat,
+}
+
+#[derive(Debug, Args, Clone)]
+#[command(flatten_help = true)]
+pub struct CorrelateArgs {
+    /// Hdf5 file
+    #[arg(required(true))]
+    pub src_hdf5: PathBuf,
+
+    /// Reference dataset
+    #[arg(required(true))]
+    pub first: String,
+
+    /// Dataset to find the lag of, the lag is positive when it lags behind the first
+    #[arg(required(true))]
+    pub second: String,
+
+    #[arg(short, long, default_value("0"))]
+    pub axis: usize,
+
+    /// Only search for the best lag, of the strongest positive or negative correlation, within
+    /// ±N samples
+    #[arg(long, value_name("N"))]
+    pub max_lag: Option<usize>,
+
+    /// Sample rate of both datasets, to report the lag in seconds, the rate of the grid when
+    /// resampling
+    #[arg(long, value_name("Hz"))]
+    pub sample_rate: Option<f64>,
+
+    /// Plot the cross-correlation against the lag
+    #[arg(long)]
+    pub plot: bool,
+
+    #[command(flatten)]
+    pub resampling: Resampling,
+
+    /// Upper bound on the memory used for each read, both datasets are kept in memory
+    #[arg(long, default_value("64"), value_name("MiB"))]
+    pub memory_budget: usize,
+
+    /// Use the stored values as they are, instead of unpacking datasets with CF attributes
+    #[arg(long)]
+    pub raw: bool,
+}
+
+// The flattened argument groups below are described in plain comments, as clap would use a doc
+// comment as the about text of the commands flattening them.
+
+// Limits that a series should stay within, each run of samples beyond a limit is an event
+#[derive(Debug, Args, Clone)]
+pub struct Thresholds {
+    /// Detect excursions above this value
+    #[arg(long, value_name("X"))]
+    pub above: Option<f64>,
+
+    /// Detect excursions below this value
+    #[arg(long, value_name("Y"))]
+    pub below: Option<f64>,
+
+    /// Ignore excursions shorter than N samples
+    #[arg(long, default_value("1"), value_name("N"))]
+    pub min_duration: usize,
+}
+
+// Alignment of datasets with their own timestamps onto a common time grid
+#[derive(Debug, Args, Clone)]
+pub struct Resampling {
+    /// Resample the series onto a common time grid with this period, e.g. `1ms`, `50us` or
+    /// `0.5s`, over the time range covered by all of them
+    #[arg(long, value_name("PERIOD"), value_parser = parse_period)]
+    pub resample: Option<f64>,
+
+    /// Pair a dataset with the dataset of its timestamps, e.g. `/adc/ch1=/adc/time`. Pass many
+    /// times, datasets without a pair use the x-axis dataset
+    #[arg(long, value_name("DATASET=TIMESTAMPS"), value_parser = parse_timestamps_pair)]
+    pub timestamps: Vec<(String, String)>,
+
+    /// Unit of the timestamps
+    #[arg(long, default_value_t = TimeUnit::S, value_name("UNIT"))]
+    pub time_unit: TimeUnit,
+
+    /// How the values between two samples are resampled
+    #[arg(long, default_value_t = Interpolation::Linear, value_name("METHOD"))]
+    pub interpolation: Interpolation,
+}
+
+/// Print the paths of the datasets matching all of the criteria, one per line, without
+/// reading any data
+#[derive(Debug, Args, Clone)]
+#[command(flatten_help = true)]
+pub struct FindArgs {
+    #[arg(required(true))]
+    pub src_hdf5: PathBuf,
+
+    /// Regex matched anywhere in the dataset path, e.g. `batt.*volt` or `/adc/ch[0-3]$`
+    #[arg(short, long, value_name("REGEX"))]
+    pub name: Option<regex::Regex>,
+
+    /// Numeric data type, one of `i8`, `i16`, `i32`, `i64`, `u8`, `u16`, `u32`, `u64`, `f32` or
+    /// `f64`
+    #[arg(short, long, value_name("TYPE"))]
+    pub dtype: Option<NativePrimitiveType>,
+
+    /// Number of dimensions
+    #[arg(long, value_name("N"))]
+    pub ndim: Option<usize>,
+
+    /// Attribute with the given value, e.g. `units=V`, or with any value, e.g. `units`, may be
+    /// repeated
+    #[arg(
+        short,
+        long = "attr",
+        value_name("NAME[=VALUE]"),
+        value_parser(parse_attr_match)
+    )]
+    pub attrs: Vec<AttrMatch>,
+
+    /// Minimum number of samples, the length of the first dimension
+    #[arg(long, value_name("N"))]
+    pub min_len: Option<usize>,
+}
+
+/// Compare two files by their structure, types, shapes, attributes and the values of numeric
+/// datasets, fails if they differ
+#[derive(Debug, Args, Clone)]
+#[command(flatten_help = true)]
+pub struct DiffArgs {
+    #[arg(required(true))]
+    pub first: PathBuf,
+
+    #[arg(required(true))]
+    pub second: PathBuf,
+
+    /// Absolute tolerance, elements differ by more than `atol + rtol * |second|`
+    #[arg(long, default_value("0"))]
+    pub atol: f64,
+
+    /// Relative tolerance, elements differ by more than `atol + rtol * |second|`
+    #[arg(long, default_value("0"))]
+    pub rtol: f64,
+
+    /// Compare only the structure, types, shapes and attributes, without reading any data
+    #[arg(long)]
+    pub structure_only: bool,
+
+    /// Upper bound on the memory used for reading the datasets
+    #[arg(long, default_value("64"), value_name("MiB"))]
+    pub memory_budget: usize,
+}
+
+/// Print a SHA-256 digest of the type, shape and contents of each dataset, and of the
+/// whole file, which do not depend on chunking or compression
+#[derive(Debug, Args, Clone)]
+#[command(flatten_help = true)]
+pub struct ChecksumArgs {
+    #[arg(required(true))]
+    pub src_hdf5: PathBuf,
+
+    /// Upper bound on the memory used for reading each dataset
+    #[arg(long, default_value("64"), value_name("MiB"))]
+    pub memory_budget: usize,
 }
 
 #[derive(Debug, Args, Clone)]
@@ -119,7 +460,50 @@ pub struct InspectArgs {
     #[arg(required(true))]
     pub src_hdf5: PathBuf,
 
-    /// How many samples to print from each dimension and axis
+    /// Group or dataset to start at, e.g. `/logger/adc`, the root group by default
+    pub path: Option<String>,
+
+    /// How many samples to print from each dimension and axis, and values of each attribute
     #[arg(short, long, default_value("10"))]
     pub preview_samples: usize,
+
+    /// Print the hierarchy like `tree`, with the type, shape and size of each dataset, without
+    /// reading any data
+    #[arg(short, long, visible_alias("ls"))]
+    pub tree: bool,
+
+    /// Output format, `json` writes the groups, datasets, attributes and links without data
+    #[arg(short, long, default_value_t = InspectFormat::Text, conflicts_with("tree"))]
+    pub format: InspectFormat,
+
+    /// Print only the attributes of the groups and datasets, without reading any data
+    #[arg(long, conflicts_with_all(["tree", "format"]))]
+    pub attrs_only: bool,
+
+    /// List the datasets sorted by their size on disk, largest first, with their compression
+    /// ratio and filters, without reading any data
+    #[arg(long, conflicts_with_all(["tree", "format", "attrs_only"]))]
+    pub sort_by_size: bool,
+
+    /// Open the files that external links point to and list their targets
+    #[arg(long, conflicts_with_all(["tree", "format", "attrs_only", "sort_by_size"]))]
+    pub follow_external: bool,
+
+    /// List the soft and external links whose targets do not exist
+    #[arg(long, conflicts_with_all(["tree", "format", "attrs_only", "sort_by_size"]))]
+    pub dangling: bool,
+
+    /// List only the objects whose paths match the glob, e.g. `/adc/*` or `*_raw`, may be
+    /// repeated
+    #[arg(long, value_name("GLOB"))]
+    pub include: Vec<String>,
+
+    /// Leave out the objects whose paths match the glob and everything below them, may be
+    /// repeated
+    #[arg(long, value_name("GLOB"))]
+    pub exclude: Vec<String>,
+
+    /// List objects down to N levels below the starting group
+    #[arg(long, value_name("N"))]
+    pub max_depth: Option<usize>,
 }
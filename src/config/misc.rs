@@ -30,3 +30,156 @@ impl std::fmt::Display for ColorWhen {
             .fmt(f)
     }
 }
+
+/// How NaN and infinite values are treated when computing statistics
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NanPolicy {
+    /// Include them, so a single NaN makes the statistics NaN
+    Propagate,
+    /// Count them but exclude them from the statistics
+    #[default]
+    Skip,
+    /// Fail on the first NaN or infinite value
+    Error,
+}
+
+impl std::fmt::Display for NanPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Output format of statistics
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// Coloured, human readable listing of each dataset
+    #[default]
+    Text,
+    /// Aligned table with a row per dataset or lane
+    Table,
+    Json,
+    Csv,
+}
+
+impl std::fmt::Display for StatsFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Transform applied to the samples of a series before plotting and computing statistics
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Transform {
+    /// Difference to the previous sample
+    Diff,
+    /// Difference to the previous sample divided by the difference on the x-axis
+    Derivative,
+    /// Cumulative sum
+    Cumsum,
+    /// Cumulative integral over the x-axis with the trapezoidal rule
+    Integral,
+}
+
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Output format of detected events
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EventsFormat {
+    /// Aligned table with a row per event
+    #[default]
+    Table,
+    Json,
+}
+
+impl std::fmt::Display for EventsFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// How values between two samples are estimated when resampling
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The value of the closest sample in time
+    Nearest,
+    /// Linear interpolation between the surrounding samples
+    #[default]
+    Linear,
+    /// Zero-order hold, the value of the last sample at or before the time
+    Zoh,
+}
+
+impl std::fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Unit of the values in timestamp datasets
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeUnit {
+    #[default]
+    S,
+    Ms,
+    Us,
+    Ns,
+}
+
+impl TimeUnit {
+    /// Seconds per unit
+    pub fn scale(&self) -> f64 {
+        match self {
+            TimeUnit::S => 1.0,
+            TimeUnit::Ms => 1e-3,
+            TimeUnit::Us => 1e-6,
+            TimeUnit::Ns => 1e-9,
+        }
+    }
+}
+
+impl std::fmt::Display for TimeUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Output format of the inspect command
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum InspectFormat {
+    /// Human readable, coloured text
+    #[default]
+    Text,
+    /// The structure of the file as one nested JSON document
+    Json,
+}
+
+impl std::fmt::Display for InspectFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
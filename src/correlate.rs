@@ -0,0 +1,202 @@
+use anyhow::{bail, Context};
+use rustfft::{num_complex::Complex, FftPlanner};
+use termcolor::{Color, StandardStream};
+
+use crate::{
+    config::{Config, CorrelateArgs},
+    my_hdf5::{cf::Packing, chunked::read_samples},
+    plot::{plot, Overlays, Series},
+    resample::Alignment,
+    util::print_colored_quoted,
+};
+
+pub fn handle_correlate_cmd(args: &CorrelateArgs, cfg: &Config) -> anyhow::Result<()> {
+    log::trace!("{args:?}");
+    log::debug!("opening: {:?}", args.src_hdf5.as_path());
+    let file = hdf5::File::open(args.src_hdf5.as_path())?;
+
+    // Both series are needed in full for the FFT, the budget only bounds the blocks read
+    let memory_budget = args.memory_budget << 20;
+    let alignment = Alignment::new(
+        &file,
+        &args.resampling,
+        &[args.first.as_str(), args.second.as_str()],
+        None,
+    )?;
+    let mut series = vec![];
+    for name in [&args.first, &args.second] {
+        let dataset = file.dataset(name)?;
+        let packing = match args.raw {
+            true => None,
+            false => Packing::from_dataset(&dataset)?,
+        };
+        let samples = match &alignment {
+            Some(alignment) => {
+                let mut samples = vec![];
+                alignment
+                    .resample_dataset(
+                        &file,
+                        &dataset,
+                        packing.as_ref(),
+                        args.axis,
+                        memory_budget,
+                        |_, _, x| {
+                            samples.push(x);
+                            Ok(())
+                        },
+                    )
+                    .map(|_| samples)
+            }
+            None => read_samples(&dataset, packing.as_ref(), args.axis, memory_budget),
+        }
+        .with_context(|| format!("Failed reading {name}"))?;
+        if samples.len() < 2 {
+            bail!("{name} must have at least 2 samples to correlate");
+        }
+        series.push(samples);
+    }
+    let (a, b) = (&series[0], &series[1]);
+
+    let correlation = cross_correlation(a, b);
+    let max_lag = args.max_lag.unwrap_or(usize::MAX);
+    // The strongest correlation, which is negative if the second is inverted
+    let Some((best_lag, best)) = correlation
+        .iter()
+        .filter(|(lag, c)| lag.unsigned_abs() <= max_lag && c.is_finite())
+        .max_by(|(_, c1), (_, c2)| c1.abs().total_cmp(&c2.abs()))
+        .copied()
+    else {
+        bail!("The cross-correlation is undefined, are both datasets constant?");
+    };
+    let (pearson_best, overlap_best) = pearson_at_lag(a, b, best_lag);
+    let (pearson_zero, overlap_zero) = pearson_at_lag(a, b, 0);
+    // Resampled series have the rate of the grid
+    let sample_rate = args
+        .sample_rate
+        .or(alignment.as_ref().map(|a| 1.0 / a.grid.period));
+
+    let mut stdout = StandardStream::stdout(cfg.color_when());
+    let out = &mut stdout;
+    print_colored_quoted(
+        out,
+        Color::Cyan,
+        "Correlation:",
+        format!("{} and {}", args.first, args.second),
+    )?;
+    let lag_txt = match sample_rate {
+        Some(rate) => format!("{best_lag} samples ({} s)", best_lag as f64 / rate),
+        None => format!("{best_lag} samples"),
+    };
+    print_colored_quoted(out, Color::Yellow, "  - Best lag: ", lag_txt)?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Normalized cross-correlation: ",
+        format!("{best:.4}"),
+    )?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Pearson coefficient at best lag: ",
+        format!("{pearson_best:.4} over {overlap_best} samples"),
+    )?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Pearson coefficient at zero lag: ",
+        format!("{pearson_zero:.4} over {overlap_zero} samples"),
+    )?;
+
+    if args.plot {
+        let (x, values): (Vec<f64>, Vec<f64>) = correlation
+            .iter()
+            .filter(|(lag, _)| lag.unsigned_abs() <= max_lag)
+            .map(|&(lag, c)| (sample_rate.map_or(lag as f64, |r| lag as f64 / r), c))
+            .unzip();
+        let x_title = match sample_rate {
+            Some(_) => "Lag [s]",
+            None => "Lag [samples]",
+        };
+        let series = Series {
+            name: "Cross-correlation".to_owned(),
+            x: Some(x),
+            values,
+        };
+        plot(vec![series], Overlays::default(), x_title)?;
+    }
+
+    Ok(())
+}
+
+/// Normalized cross-correlation of the mean-removed series, computed with FFTs in O(n log n)
+///
+/// Returns pairs of (lag, coefficient) for every lag from `-(a.len() - 1)` to `b.len() - 1`,
+/// the coefficient at lag k correlates `a[n]` with `b[n + k]`, so the lag is positive when `b`
+/// lags behind `a`. NaN and infinite values are replaced by the mean.
+pub fn cross_correlation(a: &[f64], b: &[f64]) -> Vec<(isize, f64)> {
+    let (a, b) = (centered(a), centered(b));
+    let n = (a.len() + b.len() - 1).next_power_of_two();
+    let to_complex = |values: &[f64]| -> Vec<Complex<f64>> {
+        let mut buffer: Vec<Complex<f64>> = values.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        buffer.resize(n, Complex::new(0.0, 0.0));
+        buffer
+    };
+    let (mut fa, mut fb) = (to_complex(&a), to_complex(&b));
+
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(n);
+    fft.process(&mut fa);
+    fft.process(&mut fb);
+    let mut spectrum: Vec<Complex<f64>> = fa.iter().zip(&fb).map(|(x, y)| x.conj() * *y).collect();
+    planner.plan_fft_inverse(n).process(&mut spectrum);
+
+    // The inverse FFT is unnormalized, so it is scaled by n
+    let energy = |values: &[f64]| values.iter().map(|x| x * x).sum::<f64>();
+    let norm = (energy(&a) * energy(&b)).sqrt() * n as f64;
+    let (first_lag, last_lag) = (-(a.len() as isize - 1), b.len() as isize - 1);
+    (first_lag..=last_lag)
+        .map(|lag| {
+            // Negative lags wrap around to the end of the circular correlation
+            let index = lag.rem_euclid(n as isize) as usize;
+            (lag, spectrum[index].re / norm)
+        })
+        .collect()
+}
+
+fn centered(values: &[f64]) -> Vec<f64> {
+    let (sum, count) = values
+        .iter()
+        .filter(|x| x.is_finite())
+        .fold((0.0, 0usize), |(sum, count), x| (sum + x, count + 1));
+    let mean = sum / count as f64;
+    values
+        .iter()
+        .map(|&x| if x.is_finite() { x - mean } else { 0.0 })
+        .collect()
+}
+
+/// Pearson correlation coefficient of the overlapping pairs `a[n]` and `b[n + lag]` where both
+/// values are finite, and the number of those pairs
+pub fn pearson_at_lag(a: &[f64], b: &[f64], lag: isize) -> (f64, usize) {
+    let start = (-lag).max(0) as usize;
+    let end = (a.len() as isize).min(b.len() as isize - lag).max(0) as usize;
+
+    // Co-moments updated incrementally like Welford's method
+    let (mut count, mut mean_x, mut mean_y) = (0usize, 0.0, 0.0);
+    let (mut m2_x, mut m2_y, mut c_xy) = (0.0, 0.0, 0.0);
+    for n in start..end {
+        let (x, y) = (a[n], b[(n as isize + lag) as usize]);
+        if !(x.is_finite() && y.is_finite()) {
+            continue;
+        }
+        count += 1;
+        let dx = x - mean_x;
+        let dy = y - mean_y;
+        mean_x += dx / count as f64;
+        mean_y += dy / count as f64;
+        m2_x += dx * (x - mean_x);
+        m2_y += dy * (y - mean_y);
+        c_xy += dx * (y - mean_y);
+    }
+    (c_xy / (m2_x * m2_y).sqrt(), count)
+}
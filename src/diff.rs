@@ -0,0 +1,386 @@
+//! Differences between two files, in their structure, types, shapes, attributes and values
+
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::bail;
+use hdf5::{Dataset, H5Type};
+use termcolor::{Color, StandardStream};
+
+use crate::{
+    config::{Config, DiffArgs},
+    my_hdf5::{
+        chunked::elements,
+        inspect::{member_path, AttributeInfo, DatasetInfo, GroupInfo, ObjectInfo},
+        link::Link,
+        util::NativePrimitiveType,
+    },
+    util::{print_color, print_colored_quoted},
+};
+
+/// An object of a file, by its path
+#[derive(Debug, Clone, Copy)]
+enum Object<'a> {
+    Group(&'a GroupInfo),
+    Dataset(&'a DatasetInfo),
+    NamedDatatype,
+    /// Soft or external link, which is compared by its target
+    Link(&'a Link),
+    /// Object that failed to be read, which is not compared
+    Unreadable,
+}
+
+impl Object<'_> {
+    fn kind(&self) -> &'static str {
+        match self {
+            Object::Group(_) => "group",
+            Object::Dataset(_) => "dataset",
+            Object::NamedDatatype => "named datatype",
+            Object::Link(Link::External { .. }) => "external link",
+            Object::Link(_) => "soft link",
+            Object::Unreadable => "unreadable object",
+        }
+    }
+}
+
+/// The objects below the group by path, objects with several hard links only once
+fn collect_objects<'a>(group: &'a GroupInfo, objects: &mut BTreeMap<String, Object<'a>>) {
+    for member in &group.members {
+        let path = member_path(&group.path, &member.name);
+        let object = match (&member.object, &member.same_as, &member.error) {
+            (Some(ObjectInfo::Group(subgroup)), ..) => {
+                collect_objects(subgroup, objects);
+                Object::Group(subgroup)
+            }
+            (Some(ObjectInfo::Dataset(dataset)), ..) => Object::Dataset(dataset),
+            (Some(ObjectInfo::NamedDatatype), ..) => Object::NamedDatatype,
+            (None, Some(_), _) => continue,
+            (None, None, Some(_)) => Object::Unreadable,
+            (None, None, None) => Object::Link(&member.link),
+        };
+        objects.insert(path, object);
+    }
+}
+
+/// Elements are equal if they differ by at most `atol + rtol * |b|`, like `numpy.isclose`
+#[derive(Debug, Clone, Copy)]
+struct Tolerance {
+    atol: f64,
+    rtol: f64,
+}
+
+impl Tolerance {
+    fn differ<T: Compared>(&self, a: T, b: T) -> bool {
+        if a.same(b) {
+            return false;
+        }
+        // Also false if only one of them is NaN
+        let close = a.difference(b) <= self.atol + self.rtol * b.magnitude();
+        !close
+    }
+}
+
+/// Values that are compared, integers exactly and floats within the tolerance
+trait Compared: Copy + fmt::Display {
+    fn same(self, other: Self) -> bool;
+    /// Absolute difference, NaN if either is NaN
+    fn difference(self, other: Self) -> f64;
+    fn magnitude(self) -> f64;
+}
+
+impl Compared for f64 {
+    fn same(self, other: Self) -> bool {
+        self == other || (self.is_nan() && other.is_nan())
+    }
+
+    fn difference(self, other: Self) -> f64 {
+        (self - other).abs()
+    }
+
+    fn magnitude(self) -> f64 {
+        self.abs()
+    }
+}
+
+/// Integers of any type, which all fit in an `i128` along with their differences
+impl Compared for i128 {
+    fn same(self, other: Self) -> bool {
+        self == other
+    }
+
+    fn difference(self, other: Self) -> f64 {
+        (self - other).unsigned_abs() as f64
+    }
+
+    fn magnitude(self) -> f64 {
+        self.unsigned_abs() as f64
+    }
+}
+
+/// Differences between the values of two datasets of the same shape
+#[derive(Debug)]
+struct ValueDiff<T> {
+    elements: usize,
+    differing: usize,
+    /// Largest absolute difference, the index of the element and the two values
+    largest: Option<(f64, usize, T, T)>,
+}
+
+impl<T: Compared> ValueDiff<T> {
+    fn push(&mut self, i: usize, a: T, b: T, tolerance: Tolerance) {
+        self.elements += 1;
+        if !tolerance.differ(a, b) {
+            return;
+        }
+        self.differing += 1;
+        let difference = a.difference(b);
+        if difference.is_nan() {
+            return;
+        }
+        if self
+            .largest
+            .is_none_or(|(largest, ..)| difference > largest)
+        {
+            self.largest = Some((difference, i, a, b));
+        }
+    }
+
+    /// Text of the differences, `None` if there are none
+    fn summary(&self, shape: &[usize]) -> Option<String> {
+        match self.largest {
+            Some((largest, i, x, y)) => Some(format!(
+                "{} of {} elements differ, the largest is {largest} at {:?} ({x} != {y})",
+                self.differing,
+                self.elements,
+                unravel(i, shape)
+            )),
+            None if self.differing > 0 => Some(format!(
+                "{} of {} elements differ",
+                self.differing, self.elements
+            )),
+            None => None,
+        }
+    }
+}
+
+/// Compare the values of two datasets of the same shape, both in row-major order
+fn compare_values<T: Compared>(
+    a: impl Iterator<Item = hdf5::Result<T>>,
+    b: impl Iterator<Item = hdf5::Result<T>>,
+    tolerance: Tolerance,
+) -> anyhow::Result<ValueDiff<T>> {
+    let mut diff = ValueDiff {
+        elements: 0,
+        differing: 0,
+        largest: None,
+    };
+    for (i, (x, y)) in a.zip(b).enumerate() {
+        diff.push(i, x?, y?, tolerance);
+    }
+    Ok(diff)
+}
+
+/// The elements of a dataset in row-major order, read in blocks of at most `memory_budget`
+/// bytes, or the value of a scalar dataset
+fn values<T: H5Type + Copy>(
+    dataset: &Dataset,
+    memory_budget: usize,
+) -> Box<dyn Iterator<Item = hdf5::Result<T>> + '_> {
+    match dataset.ndim() {
+        0 => Box::new(std::iter::once(dataset.read_scalar::<T>())),
+        _ => Box::new(elements::<T>(dataset, memory_budget)),
+    }
+}
+
+/// The elements of an integer dataset read in its own type, `None` for float datasets
+fn integers(
+    dataset: &Dataset,
+    native: NativePrimitiveType,
+    memory_budget: usize,
+) -> Option<Box<dyn Iterator<Item = hdf5::Result<i128>> + '_>> {
+    fn widened<'a, T: H5Type + Copy + Into<i128>>(
+        dataset: &'a Dataset,
+        memory_budget: usize,
+    ) -> Box<dyn Iterator<Item = hdf5::Result<i128>> + 'a> {
+        Box::new(values::<T>(dataset, memory_budget).map(|x| x.map(Into::into)))
+    }
+
+    let integers = match native {
+        NativePrimitiveType::Integer8b => widened::<i8>(dataset, memory_budget),
+        NativePrimitiveType::Integer16b => widened::<i16>(dataset, memory_budget),
+        NativePrimitiveType::UnsignedInteger8b => widened::<u8>(dataset, memory_budget),
+        NativePrimitiveType::UnsignedInteger16b => widened::<u16>(dataset, memory_budget),
+        NativePrimitiveType::Integer32b => widened::<i32>(dataset, memory_budget),
+        NativePrimitiveType::Integer64b => widened::<i64>(dataset, memory_budget),
+        NativePrimitiveType::UnsignedInteger32b => widened::<u32>(dataset, memory_budget),
+        NativePrimitiveType::UnsignedInteger64b => widened::<u64>(dataset, memory_budget),
+        NativePrimitiveType::Pointer(_) => widened::<u64>(dataset, memory_budget),
+        NativePrimitiveType::Float32b | NativePrimitiveType::Float64b => return None,
+    };
+    Some(integers)
+}
+
+/// Index of the `i`'th element in row-major order of an array of the given shape
+fn unravel(mut i: usize, shape: &[usize]) -> Vec<usize> {
+    let mut index = vec![0; shape.len()];
+    for (axis, &len) in shape.iter().enumerate().rev() {
+        index[axis] = i % len.max(1);
+        i /= len.max(1);
+    }
+    index
+}
+
+/// Prints the differences and counts them
+struct Report<'a> {
+    first: String,
+    second: String,
+    differences: usize,
+    out: &'a mut StandardStream,
+}
+
+impl Report<'_> {
+    fn difference(&mut self, path: &str, text: impl std::fmt::Display) -> anyhow::Result<()> {
+        self.differences += 1;
+        print_colored_quoted(self.out, Color::Yellow, format!("{path}: "), text)
+    }
+
+    fn attributes(
+        &mut self,
+        path: &str,
+        first: &[AttributeInfo],
+        second: &[AttributeInfo],
+    ) -> anyhow::Result<()> {
+        for a in first {
+            match second.iter().find(|b| b.name == a.name) {
+                None => {
+                    self.difference(path, format!("attribute {} only in {}", a.name, self.first))?
+                }
+                Some(b) if a.dtype != b.dtype || a.shape != b.shape => self.difference(
+                    path,
+                    format!(
+                        "attribute {} is {} {:?} != {} {:?}",
+                        a.name, a.dtype, a.shape, b.dtype, b.shape
+                    ),
+                )?,
+                Some(b) if a.value != b.value => self.difference(
+                    path,
+                    format!("attribute {} is {} != {}", a.name, a.value, b.value),
+                )?,
+                Some(_) => (),
+            }
+        }
+        for b in second {
+            if !first.iter().any(|a| a.name == b.name) {
+                self.difference(
+                    path,
+                    format!("attribute {} only in {}", b.name, self.second),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn handle_diff_cmd(args: &DiffArgs, cfg: &Config) -> anyhow::Result<()> {
+    log::trace!("{args:?}");
+    let first_file = hdf5::File::open(args.first.as_path())?;
+    let second_file = hdf5::File::open(args.second.as_path())?;
+
+    let first_root = GroupInfo::read(&first_file)?;
+    let second_root = GroupInfo::read(&second_file)?;
+    let mut first = BTreeMap::new();
+    collect_objects(&first_root, &mut first);
+    let mut second = BTreeMap::new();
+    collect_objects(&second_root, &mut second);
+
+    let tolerance = Tolerance {
+        atol: args.atol,
+        rtol: args.rtol,
+    };
+    let mut stdout = StandardStream::stdout(cfg.color_when());
+    let mut report = Report {
+        first: args.first.display().to_string(),
+        second: args.second.display().to_string(),
+        differences: 0,
+        out: &mut stdout,
+    };
+    report.attributes("/", &first_root.attributes, &second_root.attributes)?;
+
+    for (path, a) in &first {
+        let Some(b) = second.get(path) else {
+            report.difference(path, format!("{} only in {}", a.kind(), report.first))?;
+            continue;
+        };
+        match (a, b) {
+            (Object::Group(a), Object::Group(b)) => {
+                report.attributes(path, &a.attributes, &b.attributes)?
+            }
+            (Object::Dataset(a), Object::Dataset(b)) => {
+                report.attributes(path, &a.attributes, &b.attributes)?;
+                if a.dtype != b.dtype {
+                    report.difference(path, format!("type {} != {}", a.dtype, b.dtype))?;
+                }
+                if a.shape != b.shape {
+                    report.difference(path, format!("shape {:?} != {:?}", a.shape, b.shape))?;
+                    continue;
+                }
+                if args.structure_only {
+                    continue;
+                }
+                let (a, b) = (first_file.dataset(path)?, second_file.dataset(path)?);
+                let native_a = NativePrimitiveType::try_from_dtype(&a.dtype()?);
+                let native_b = NativePrimitiveType::try_from_dtype(&b.dtype()?);
+                let (Some(native_a), Some(native_b)) = (native_a, native_b) else {
+                    log::debug!("Not comparing the values of {path}, they are not numeric");
+                    continue;
+                };
+                // Integers are compared exactly, as 64-bit integers do not all fit in a float
+                let memory_budget = (args.memory_budget << 20) / 2;
+                let summary = match (
+                    integers(&a, native_a, memory_budget),
+                    integers(&b, native_b, memory_budget),
+                ) {
+                    (Some(x), Some(y)) => compare_values(x, y, tolerance)?.summary(&a.shape()),
+                    _ => compare_values(
+                        values::<f64>(&a, memory_budget),
+                        values::<f64>(&b, memory_budget),
+                        tolerance,
+                    )?
+                    .summary(&a.shape()),
+                };
+                if let Some(summary) = summary {
+                    report.difference(path, summary)?;
+                }
+            }
+            (Object::Link(a), Object::Link(b)) if a != b => {
+                report.difference(path, format!("link {a:?} != {b:?}"))?
+            }
+            (Object::Link(_), Object::Link(_)) => (),
+            (Object::NamedDatatype, Object::NamedDatatype) => (),
+            // The errors are reported when reading the files
+            (Object::Unreadable, _) | (_, Object::Unreadable) => (),
+            (a, b) => report.difference(
+                path,
+                format!(
+                    "{} in {}, {} in {}",
+                    a.kind(),
+                    report.first,
+                    b.kind(),
+                    report.second
+                ),
+            )?,
+        }
+    }
+    for (path, b) in &second {
+        if !first.contains_key(path) {
+            report.difference(path, format!("{} only in {}", b.kind(), report.second))?;
+        }
+    }
+
+    match report.differences {
+        0 => {
+            print_color(report.out, Color::Green, "No differences")?;
+            Ok(())
+        }
+        n => bail!("The files differ in {n} places"),
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::Write;
+
+use anyhow::{bail, Context};
+use hdf5::Dataset;
+use serde::Serialize;
+use termcolor::{Color, StandardStream};
+
+use crate::{
+    config::{misc::EventsFormat, Config, EventsArgs, Thresholds},
+    my_hdf5::{
+        cf::Packing,
+        chunked::{for_each_mapped, for_each_sample, sample_count, Sample},
+        util::{type_name, with_native_type, NativePrimitiveType},
+    },
+    stats::transform::transformed,
+    util::{print_colored_quoted, print_table},
+};
+
+pub fn handle_events_cmd(args: &EventsArgs, cfg: &Config) -> anyhow::Result<()> {
+    log::trace!("{args:?}");
+    if !args.thresholds.is_set() {
+        bail!("Nothing to detect, pass --above and/or --below");
+    }
+
+    log::debug!("opening: {:?}", args.src_hdf5.as_path());
+    let file = hdf5::File::open(args.src_hdf5.as_path())?;
+    let dataset = file.dataset(&args.dataset)?;
+    let x_axis = match &args.x_axis {
+        Some(name) => Some(file.dataset(name)?),
+        None => None,
+    };
+    let packing = match args.raw {
+        true => None,
+        false => Packing::from_dataset(&dataset)?,
+    };
+
+    let mut detector = EventDetector::new(&args.thresholds);
+    detect_events(
+        &dataset,
+        packing.as_ref(),
+        x_axis.as_ref(),
+        args.axis,
+        args.memory_budget << 20,
+        &mut detector,
+    )
+    .with_context(|| format!("Failed scanning {}", args.dataset))?;
+    let events = detector.finish();
+    log::info!("Found {} events in {}", events.len(), dataset.name());
+
+    let mut stdout = StandardStream::stdout(cfg.color_when());
+    match args.format {
+        EventsFormat::Table => {
+            print_colored_quoted(&mut stdout, Color::Cyan, "Dataset:", dataset.name())?;
+            print_events_table(&mut stdout, &events, x_axis.is_some())?;
+        }
+        EventsFormat::Json => {
+            let report = EventsReport {
+                dataset: dataset.name(),
+                above: args.thresholds.above,
+                below: args.thresholds.below,
+                min_duration: args.thresholds.min_duration,
+                events,
+            };
+            serde_json::to_writer_pretty(&mut stdout, &report)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Scan a 1-D dataset, or a 2-D dataset folded along `axis`, for events while reading at most
+/// `memory_budget` bytes at a time
+pub fn detect_events(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    x_axis: Option<&Dataset>,
+    axis: usize,
+    memory_budget: usize,
+    detector: &mut EventDetector,
+) -> anyhow::Result<()> {
+    let dtype = dataset.dtype()?;
+    let Some(native) = NativePrimitiveType::try_from_dtype(&dtype) else {
+        bail!(
+            "{} is not numeric, its type is {}",
+            dataset.name(),
+            type_name(&dtype)
+        );
+    };
+    with_native_type!(
+        native,
+        T,
+        detect_in_samples::<T>(dataset, packing, x_axis, axis, memory_budget, detector)
+    )
+}
+
+fn detect_in_samples<T: Sample>(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    x_axis: Option<&Dataset>,
+    axis: usize,
+    memory_budget: usize,
+    detector: &mut EventDetector,
+) -> anyhow::Result<()> {
+    let with_x = x_axis.is_some();
+    let len = sample_count(dataset, axis)?;
+    match packing {
+        Some(packing) => for_each_mapped::<T, _, _, _>(
+            dataset,
+            axis,
+            memory_budget,
+            |x| packing.unpack_sample(x),
+            transformed(None, x_axis, len, memory_budget, |i, t, y| {
+                detector.push(i, with_x.then_some(t), y.to_f64());
+                Ok(())
+            })?,
+        ),
+        None => for_each_sample::<T, _>(
+            dataset,
+            axis,
+            memory_budget,
+            transformed(None, x_axis, len, memory_budget, |i, t, y| {
+                detector.push(i, with_x.then_some(t), y.to_f64());
+                Ok(())
+            })?,
+        ),
+    }
+}
+
+impl Thresholds {
+    pub fn is_set(&self) -> bool {
+        self.above.is_some() || self.below.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Excursion {
+    Above,
+    Below,
+}
+
+/// A run of consecutive samples beyond one of the thresholds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    pub kind: Excursion,
+    pub start_index: usize,
+    /// Index of the last sample of the event
+    pub end_index: usize,
+    pub samples: usize,
+    /// Position of the first sample on the x-axis, if there is one
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    pub duration: Option<f64>,
+    /// The largest value of excursions above and the smallest of excursions below
+    pub peak: f64,
+    pub peak_index: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct EventsReport {
+    dataset: String,
+    above: Option<f64>,
+    below: Option<f64>,
+    min_duration: usize,
+    events: Vec<Event>,
+}
+
+/// Streaming detection of [`Event`]s
+///
+/// NaN values end an event, infinite values are compared like any other and so are beyond a
+/// threshold on their side, e.g. `+inf` is above any threshold.
+#[derive(Debug, Clone)]
+pub struct EventDetector {
+    above: Option<f64>,
+    below: Option<f64>,
+    min_duration: usize,
+    current: Option<Event>,
+    events: Vec<Event>,
+}
+
+impl EventDetector {
+    pub fn new(thresholds: &Thresholds) -> Self {
+        Self {
+            above: thresholds.above,
+            below: thresholds.below,
+            min_duration: thresholds.min_duration,
+            current: None,
+            events: vec![],
+        }
+    }
+
+    /// Add the next sample, at position `t` on the x-axis if there is one
+    pub fn push(&mut self, index: usize, t: Option<f64>, value: f64) {
+        let kind = if value.is_nan() {
+            None
+        } else if self.above.is_some_and(|above| value > above) {
+            Some(Excursion::Above)
+        } else if self.below.is_some_and(|below| value < below) {
+            Some(Excursion::Below)
+        } else {
+            None
+        };
+
+        if let Some(event) = &mut self.current {
+            if Some(event.kind) == kind && event.end_index + 1 == index {
+                event.end_index = index;
+                event.samples += 1;
+                event.end = t;
+                event.duration = t.zip(event.start).map(|(end, start)| end - start);
+                let is_peak = match event.kind {
+                    Excursion::Above => value > event.peak,
+                    Excursion::Below => value < event.peak,
+                };
+                if is_peak {
+                    event.peak = value;
+                    event.peak_index = index;
+                }
+                return;
+            }
+            self.close_current();
+        }
+
+        self.current = kind.map(|kind| Event {
+            kind,
+            start_index: index,
+            end_index: index,
+            samples: 1,
+            start: t,
+            end: t,
+            duration: t.map(|_| 0.0),
+            peak: value,
+            peak_index: index,
+        });
+    }
+
+    fn close_current(&mut self) {
+        if let Some(event) = self.current.take() {
+            if event.samples >= self.min_duration {
+                self.events.push(event);
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Event> {
+        self.close_current();
+        self.events
+    }
+}
+
+fn print_events_table(
+    out: &mut StandardStream,
+    events: &[Event],
+    with_x: bool,
+) -> anyhow::Result<()> {
+    let mut header = vec!["kind", "start index", "end index", "samples"];
+    if with_x {
+        header.extend(["start", "end", "duration"]);
+    }
+    header.extend(["peak", "peak index"]);
+
+    let fmt = |v: Option<f64>| v.map_or("-".to_owned(), |v| format!("{v:.4}"));
+    let rows: Vec<Vec<String>> = events
+        .iter()
+        .map(|event| {
+            let kind = match event.kind {
+                Excursion::Above => "above",
+                Excursion::Below => "below",
+            };
+            let mut row = vec![
+                kind.to_owned(),
+                event.start_index.to_string(),
+                event.end_index.to_string(),
+                event.samples.to_string(),
+            ];
+            if with_x {
+                row.extend([fmt(event.start), fmt(event.end), fmt(event.duration)]);
+            }
+            row.extend([format!("{:.4}", event.peak), event.peak_index.to_string()]);
+            row
+        })
+        .collect();
+    print_table(out, Color::Cyan, &header, &rows)
+}
@@ -0,0 +1,625 @@
+//! Expressions of derived signals, e.g. `diff = (/adc/ch1 - /adc/ch0) * 0.0125`
+//!
+//! An expression is evaluated element-wise over the datasets it references. Datasets are
+//! referenced by their absolute path, or by a quoted path if it contains other characters than
+//! alphanumerics, `_`, `.` and `/`, e.g. `'/adc/ch-1'`. A column of a 2-D dataset is selected
+//! with a suffix, e.g. `/imu/acc[2]`.
+//!
+//! Supported are the operators `+ - * / % ^`, parentheses, the constants `pi` and `e` and the
+//! functions listed in [`Func`].
+
+use std::{fmt, mem::size_of, str::FromStr};
+
+use anyhow::{anyhow, bail, Context};
+use hdf5::{Dataset, Hyperslab, Selection, SliceOrIndex};
+use ndarray::Array1;
+
+use crate::{
+    my_hdf5::cf::Packing,
+    resample::{check_length, Alignment},
+};
+
+/// A named expression, parsed from `name = expression` or just `expression`
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub name: String,
+    expr: Expr,
+    refs: Vec<DatasetRef>,
+}
+
+/// A 1-D dataset or a column of a 2-D dataset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatasetRef {
+    pub path: String,
+    pub column: Option<usize>,
+}
+
+impl fmt::Display for DatasetRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "{}[{column}]", self.path),
+            None => write!(f, "{}", self.path),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f64),
+    /// Index into the dataset references of the expression
+    Ref(usize),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+/// Functions available in expressions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Func {
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Log10,
+    Log2,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Floor,
+    Ceil,
+    Round,
+    Min,
+    Max,
+    Pow,
+    Hypot,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Self::Abs,
+            "sqrt" => Self::Sqrt,
+            "exp" => Self::Exp,
+            "ln" => Self::Ln,
+            "log10" => Self::Log10,
+            "log2" => Self::Log2,
+            "sin" => Self::Sin,
+            "cos" => Self::Cos,
+            "tan" => Self::Tan,
+            "asin" => Self::Asin,
+            "acos" => Self::Acos,
+            "atan" => Self::Atan,
+            "atan2" => Self::Atan2,
+            "floor" => Self::Floor,
+            "ceil" => Self::Ceil,
+            "round" => Self::Round,
+            "min" => Self::Min,
+            "max" => Self::Max,
+            "pow" => Self::Pow,
+            "hypot" => Self::Hypot,
+            _ => return None,
+        })
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Self::Atan2 | Self::Min | Self::Max | Self::Pow | Self::Hypot => 2,
+            _ => 1,
+        }
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        match self {
+            Self::Abs => args[0].abs(),
+            Self::Sqrt => args[0].sqrt(),
+            Self::Exp => args[0].exp(),
+            Self::Ln => args[0].ln(),
+            Self::Log10 => args[0].log10(),
+            Self::Log2 => args[0].log2(),
+            Self::Sin => args[0].sin(),
+            Self::Cos => args[0].cos(),
+            Self::Tan => args[0].tan(),
+            Self::Asin => args[0].asin(),
+            Self::Acos => args[0].acos(),
+            Self::Atan => args[0].atan(),
+            Self::Atan2 => args[0].atan2(args[1]),
+            Self::Floor => args[0].floor(),
+            Self::Ceil => args[0].ceil(),
+            Self::Round => args[0].round(),
+            Self::Min => args[0].min(args[1]),
+            Self::Max => args[0].max(args[1]),
+            Self::Pow => args[0].powf(args[1]),
+            Self::Hypot => args[0].hypot(args[1]),
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluate with `values` holding the current element of each dataset reference
+    fn eval(&self, values: &[f64]) -> f64 {
+        match self {
+            Expr::Num(x) => *x,
+            Expr::Ref(i) => values[*i],
+            Expr::Neg(e) => -e.eval(values),
+            Expr::Binary(op, lhs, rhs) => {
+                let (l, r) = (lhs.eval(values), rhs.eval(values));
+                match op {
+                    BinOp::Add => l + r,
+                    BinOp::Sub => l - r,
+                    BinOp::Mul => l * r,
+                    BinOp::Div => l / r,
+                    BinOp::Rem => l % r,
+                    BinOp::Pow => l.powf(r),
+                }
+            }
+            Expr::Call(func, args) => {
+                let args: Vec<f64> = args.iter().map(|a| a.eval(values)).collect();
+                func.apply(&args)
+            }
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // An `=` can only appear in quoted paths otherwise, which cannot be part of a name
+        let (name, src) = match s.split_once('=') {
+            Some((name, src)) if is_identifier(name.trim()) => (name.trim(), src),
+            _ => (s.trim(), s),
+        };
+        let mut parser = Parser {
+            src,
+            pos: 0,
+            refs: vec![],
+        };
+        // Clap only shows the outermost error, so the cause is part of the message
+        let expr = parser
+            .parse()
+            .map_err(|e| anyhow!("Invalid expression '{src}': {e:#}"))?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            expr,
+            refs: parser.refs,
+        })
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Recursive descent parser, from lowest to highest precedence:
+///
+/// ```text
+/// expr  := term (('+' | '-') term)*
+/// term  := unary (('*' | '/' | '%') unary)*
+/// unary := '-' unary | power
+/// power := atom ('^' unary)?
+/// atom  := number | path | ident | ident '(' expr (',' expr)* ')' | '(' expr ')'
+/// path  := ('/' [A-Za-z0-9_./]* | "'" [^']* "'") ('[' digits ']')?
+/// ```
+///
+/// An unquoted path takes every `/` that directly follows it, so `/a/2` is the dataset `/a/2`
+/// rather than `/a` divided by 2. Dividing an unquoted path needs a space before the `/`, e.g.
+/// `/a / 2`, or a quoted path, e.g. `'/a'/2`.
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    refs: Vec<DatasetRef>,
+}
+
+impl Parser<'_> {
+    fn parse(&mut self) -> anyhow::Result<Expr> {
+        let expr = self.expr()?;
+        if let Some(c) = self.peek() {
+            bail!("Unexpected '{c}' at position {}", self.pos);
+        }
+        Ok(expr)
+    }
+
+    /// Next non-whitespace character without consuming it
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.src[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> anyhow::Result<()> {
+        if !self.eat(c) {
+            bail!("Expected '{c}' at position {}", self.pos);
+        }
+        Ok(())
+    }
+
+    /// Consume characters while `pred` holds and return them
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        let len = self.src[start..]
+            .find(|c| !pred(c))
+            .unwrap_or(self.src.len() - start);
+        self.pos += len;
+        &self.src[start..start + len]
+    }
+
+    fn expr(&mut self) -> anyhow::Result<Expr> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                BinOp::Add
+            } else if self.eat('-') {
+                BinOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> anyhow::Result<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                BinOp::Mul
+            } else if self.eat('/') {
+                BinOp::Div
+            } else if self.eat('%') {
+                BinOp::Rem
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Expr> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> anyhow::Result<Expr> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            // Right associative, and binds tighter than a unary minus on its left: -x^2 = -(x^2)
+            return Ok(Expr::Binary(
+                BinOp::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> anyhow::Result<Expr> {
+        let next = self.peek();
+        let start = self.pos;
+        match next {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some('/') => {
+                let path = self
+                    .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '/'))
+                    .to_owned();
+                if path.len() > 1 && path.ends_with('/') {
+                    bail!(
+                        "Path '{path}' at position {start} ends with '/', write e.g. '{} / 2' \
+                         with spaces to divide it",
+                        path.trim_end_matches('/')
+                    );
+                }
+                self.dataset_ref(path)
+            }
+            Some('\'') => {
+                self.pos += 1;
+                let path = self.take_while(|c| c != '\'').to_owned();
+                self.expect('\'')?;
+                self.dataset_ref(path)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                self.take_while(|c| c.is_ascii_alphanumeric() || c == '.');
+                // Exponent sign, e.g. 1e-3
+                if self.src[start..self.pos].ends_with(['e', 'E']) && self.peek_sign() {
+                    self.pos += 1;
+                    self.take_while(|c| c.is_ascii_digit());
+                }
+                let number = &self.src[start..self.pos];
+                number
+                    .parse()
+                    .map(Expr::Num)
+                    .with_context(|| format!("Invalid number '{number}' at position {start}"))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let ident = self
+                    .take_while(|c| c.is_alphanumeric() || c == '_')
+                    .to_owned();
+                if self.eat('(') {
+                    return self.call(&ident, start);
+                }
+                match ident.as_str() {
+                    "pi" => Ok(Expr::Num(std::f64::consts::PI)),
+                    "e" => Ok(Expr::Num(std::f64::consts::E)),
+                    _ => bail!("Unknown constant '{ident}' at position {start}, dataset paths must start with '/'"),
+                }
+            }
+            Some(c) => bail!("Unexpected '{c}' at position {start}"),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+
+    fn peek_sign(&self) -> bool {
+        self.src[self.pos..].starts_with(['+', '-'])
+    }
+
+    fn call(&mut self, name: &str, start: usize) -> anyhow::Result<Expr> {
+        let Some(func) = Func::from_name(name) else {
+            bail!("Unknown function '{name}' at position {start}");
+        };
+        let mut args = vec![self.expr()?];
+        while self.eat(',') {
+            args.push(self.expr()?);
+        }
+        self.expect(')')?;
+        if args.len() != func.arity() {
+            bail!(
+                "Function '{name}' takes {} argument(s) but {} were given",
+                func.arity(),
+                args.len()
+            );
+        }
+        Ok(Expr::Call(func, args))
+    }
+
+    /// A path with an optional column suffix, e.g. `/imu/acc[2]`
+    fn dataset_ref(&mut self, path: String) -> anyhow::Result<Expr> {
+        let column = if self.eat('[') {
+            let start = self.pos;
+            let column = self.take_while(|c| c.is_ascii_digit());
+            let column = column
+                .parse()
+                .with_context(|| format!("Invalid column index at position {start}"))?;
+            self.expect(']')?;
+            Some(column)
+        } else {
+            None
+        };
+
+        let dataset_ref = DatasetRef { path, column };
+        let index = match self.refs.iter().position(|r| *r == dataset_ref) {
+            Some(index) => index,
+            None => {
+                self.refs.push(dataset_ref);
+                self.refs.len() - 1
+            }
+        };
+        Ok(Expr::Ref(index))
+    }
+}
+
+impl Expression {
+    /// The datasets referenced by the expression
+    pub fn refs(&self) -> &[DatasetRef] {
+        &self.refs
+    }
+
+    /// Length of the series the expression evaluates to, that of the referenced datasets, which
+    /// must all have the same length
+    pub fn series_len(&self, file: &hdf5::File) -> anyhow::Result<usize> {
+        let mut len = None;
+        for r in &self.refs {
+            let ref_len = check_ref(r, &open_ref(file, r)?)?;
+            match len {
+                Some(len) if len != ref_len => {
+                    bail!(
+                        "{r} has length {ref_len}, expected {len} like the other datasets of '{}'",
+                        self.name
+                    )
+                }
+                _ => len = Some(ref_len),
+            }
+        }
+        match len {
+            Some(len) => Ok(len),
+            None => bail!("'{}' does not reference any datasets", self.name),
+        }
+    }
+
+    /// Evaluate the expression element-wise over the referenced datasets, which must all have
+    /// the same length, and visit each value
+    ///
+    /// If `unpack` is set, datasets with CF packing attributes are unpacked before evaluation.
+    /// The datasets are read in blocks, such that at most `memory_budget` bytes are read at a time.
+    /// Stops at the first error returned by `f`.
+    pub fn for_each_value<F>(
+        &self,
+        file: &hdf5::File,
+        unpack: bool,
+        memory_budget: usize,
+        mut f: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(usize, f64) -> anyhow::Result<()>,
+    {
+        let len = self.series_len(file)?;
+        let mut datasets = Vec::with_capacity(self.refs.len());
+        for r in &self.refs {
+            let dataset = open_ref(file, r)?;
+            let packing = match unpack {
+                true => Packing::from_dataset(&dataset)?,
+                false => None,
+            };
+            datasets.push((dataset, packing));
+        }
+
+        let rows_per_block = (memory_budget / (size_of::<f64>() * datasets.len())).max(1);
+        let mut values = vec![0.0; datasets.len()];
+
+        for start in (0..len).step_by(rows_per_block) {
+            let end = (start + rows_per_block).min(len);
+            let blocks = datasets
+                .iter()
+                .zip(&self.refs)
+                .map(|((dataset, packing), r)| read_ref(dataset, packing.as_ref(), r, start, end))
+                .collect::<anyhow::Result<Vec<Array1<f64>>>>()?;
+
+            for i in 0..end - start {
+                for (value, block) in values.iter_mut().zip(&blocks) {
+                    *value = block[i];
+                }
+                f(start + i, self.expr.eval(&values))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like [`Self::for_each_value`], but each referenced dataset is first resampled with its
+    /// timestamps onto the grid of `alignment`, so the datasets may have different lengths
+    ///
+    /// The resampled series are kept in memory, and the value at each time `k` of the grid is
+    /// visited as `f(k, value)`.
+    pub fn for_each_aligned_value<F>(
+        &self,
+        file: &hdf5::File,
+        unpack: bool,
+        alignment: &Alignment,
+        memory_budget: usize,
+        mut f: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(usize, f64) -> anyhow::Result<()>,
+    {
+        if self.refs.is_empty() {
+            bail!("'{}' does not reference any datasets", self.name);
+        }
+        let grid_len = alignment.grid.len;
+        log::debug!(
+            "Keeping {} resampled values of '{}' in memory",
+            grid_len * self.refs.len(),
+            self.name
+        );
+
+        let mut columns = Vec::with_capacity(self.refs.len());
+        for r in &self.refs {
+            let dataset = open_ref(file, r)?;
+            let len = check_ref(r, &dataset)?;
+            let packing = match unpack {
+                true => Packing::from_dataset(&dataset)?,
+                false => None,
+            };
+            let timestamps = alignment.timestamps(file, &r.path)?;
+            check_length(&timestamps, len, &r.to_string())?;
+
+            let mut column = vec![f64::NAN; grid_len];
+            {
+                let mut sink =
+                    alignment.resampling(&timestamps, memory_budget / 2, |k, _, x| {
+                        column[k] = x;
+                        Ok(())
+                    })?;
+                let rows_per_block = (memory_budget / 2 / size_of::<f64>()).max(1);
+                for start in (0..len).step_by(rows_per_block) {
+                    let end = (start + rows_per_block).min(len);
+                    let block = read_ref(&dataset, packing.as_ref(), r, start, end)?;
+                    for (i, &x) in block.iter().enumerate() {
+                        sink(start + i, x)?;
+                    }
+                }
+            }
+            columns.push(column);
+        }
+
+        let mut values = vec![0.0; columns.len()];
+        for k in 0..grid_len {
+            for (value, column) in values.iter_mut().zip(&columns) {
+                *value = column[k];
+            }
+            f(k, self.expr.eval(&values))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Open the referenced dataset, hinting at the rule for `/` in paths if it does not exist
+fn open_ref(file: &hdf5::File, r: &DatasetRef) -> anyhow::Result<Dataset> {
+    file.dataset(&r.path)
+        .with_context(|| match r.path.rfind('/') {
+            Some(i) if i > 0 => format!(
+                "Failed opening {r}, a '/' right after a path is part of the path, write e.g. \
+             '{} / {}' with spaces to divide",
+                &r.path[..i],
+                &r.path[i + 1..]
+            ),
+            _ => format!("Failed opening {r}"),
+        })
+}
+
+/// Check that the dataset is 1-D, or 2-D with a column selected, and return its length
+fn check_ref(r: &DatasetRef, dataset: &Dataset) -> anyhow::Result<usize> {
+    let shape = dataset.shape();
+    match (shape.len(), r.column) {
+        (1, None) => (),
+        (2, Some(column)) if column < shape[1] => (),
+        (2, Some(column)) => {
+            bail!("{r}: column {column} is out of bounds for shape {shape:?}")
+        }
+        (2, None) => bail!("{}: select a column of the 2-D dataset, e.g. '{}[0]'", r, r.path),
+        (ndim, _) => bail!("{r}: expressions only support 1-D datasets and columns of 2-D datasets, got a {ndim}-D dataset"),
+    }
+    Ok(shape[0])
+}
+
+/// Read the rows `start..end` of the referenced dataset or column as floats, unpacked if
+/// `packing` is given
+fn read_ref(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    r: &DatasetRef,
+    start: usize,
+    end: usize,
+) -> anyhow::Result<Array1<f64>> {
+    let mut slices = vec![SliceOrIndex::from(start..end)];
+    slices.extend(r.column.map(SliceOrIndex::Index));
+    let selection = Selection::from(Hyperslab::from(slices));
+    let mut block = dataset
+        .read_slice_1d::<f64, _>(selection)
+        .with_context(|| format!("Failed reading {r}"))?;
+    if let Some(packing) = packing {
+        block.mapv_inplace(|x| packing.unpack(x));
+    }
+    Ok(block)
+}
@@ -0,0 +1,303 @@
+//! Digital filters applied to series before they are plotted
+//!
+//! The frequency selective filters are Butterworth IIR filters designed as cascades of
+//! second-order sections, and applied forwards and backwards (filtfilt) so that they do not shift
+//! the phase of the signal. The forward-backward pass squares the magnitude response, so the
+//! attenuation at the cut-off frequency is -6 dB instead of -3 dB.
+
+use std::{f64::consts::PI, fmt, str::FromStr};
+
+use anyhow::{bail, Context};
+
+/// Quality factor of the notch filter, the -3 dB bandwidth is the notch frequency / Q
+const NOTCH_Q: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Lowpass(f64),
+    Highpass(f64),
+    /// Lower and upper cut-off frequencies
+    Bandpass(f64, f64),
+    Notch(f64),
+    /// Centered moving average over a window of N samples
+    MovingAverage(usize),
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = s.split_once(':').with_context(|| {
+            format!("Invalid filter '{s}', expected e.g. 'lowpass:10Hz' or 'moving-average:5'")
+        })?;
+        let filter = match kind {
+            "lowpass" => Self::Lowpass(parse_frequency(arg)?),
+            "highpass" => Self::Highpass(parse_frequency(arg)?),
+            "bandpass" => {
+                let (low, high) = arg.split_once('-').with_context(|| {
+                    format!("Invalid band '{arg}', expected e.g. 'bandpass:1Hz-10Hz'")
+                })?;
+                let (low, high) = (parse_frequency(low)?, parse_frequency(high)?);
+                if low >= high {
+                    bail!("Invalid band '{arg}', the lower frequency must be below the upper");
+                }
+                Self::Bandpass(low, high)
+            }
+            "notch" => Self::Notch(parse_frequency(arg)?),
+            "moving-average" => match arg.parse() {
+                Ok(0) | Err(_) => bail!("Invalid moving average window '{arg}', expected N > 0"),
+                Ok(window) => Self::MovingAverage(window),
+            },
+            _ => bail!(
+                "Unknown filter '{kind}', expected one of lowpass, highpass, bandpass, notch or moving-average"
+            ),
+        };
+        Ok(filter)
+    }
+}
+
+/// Parse a frequency such as `50Hz`, `1.5kHz` or `50`
+fn parse_frequency(s: &str) -> anyhow::Result<f64> {
+    let (number, scale) = if let Some(number) = s.strip_suffix("kHz") {
+        (number, 1e3)
+    } else if let Some(number) = s.strip_suffix("Hz") {
+        (number, 1.0)
+    } else {
+        (s, 1.0)
+    };
+    match number.trim().parse::<f64>() {
+        Ok(f) if f > 0.0 && f.is_finite() => Ok(f * scale),
+        _ => bail!("Invalid frequency '{s}', expected e.g. '10Hz' or '1.5kHz'"),
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Lowpass(fc) => write!(f, "lowpass:{fc}Hz"),
+            Filter::Highpass(fc) => write!(f, "highpass:{fc}Hz"),
+            Filter::Bandpass(low, high) => write!(f, "bandpass:{low}Hz-{high}Hz"),
+            Filter::Notch(f0) => write!(f, "notch:{f0}Hz"),
+            Filter::MovingAverage(window) => write!(f, "moving-average:{window}"),
+        }
+    }
+}
+
+impl Filter {
+    /// Filter the values, `order` is the order of the Butterworth filters
+    ///
+    /// Each run of finite values is filtered separately, NaN and infinite values are left as NaN.
+    pub fn apply(
+        &self,
+        values: &[f64],
+        sample_rate: Option<f64>,
+        order: usize,
+    ) -> anyhow::Result<Vec<f64>> {
+        let sections = match *self {
+            Filter::MovingAverage(window) => return Ok(moving_average(values, window)),
+            _ => {
+                let Some(fs) = sample_rate else {
+                    bail!("The filter {self} requires the sample rate, see --sample-rate");
+                };
+                self.design(fs, order)?
+            }
+        };
+
+        let mut filtered = vec![f64::NAN; values.len()];
+        let mut start = 0;
+        while start < values.len() {
+            if !values[start].is_finite() {
+                start += 1;
+                continue;
+            }
+            let len = values[start..]
+                .iter()
+                .position(|x| !x.is_finite())
+                .unwrap_or(values.len() - start);
+            let end = start + len;
+            filtered[start..end].copy_from_slice(&filtfilt(&sections, &values[start..end]));
+            start = end;
+        }
+        Ok(filtered)
+    }
+
+    fn design(&self, fs: f64, order: usize) -> anyhow::Result<Vec<Section>> {
+        if order == 0 {
+            bail!("The filter order must be at least 1");
+        }
+        let nyquist = fs / 2.0;
+        let check = |f: f64| -> anyhow::Result<f64> {
+            if f >= nyquist {
+                bail!("{f} Hz of {self} is not below the Nyquist frequency {nyquist} Hz");
+            }
+            Ok(f)
+        };
+
+        let sections = match *self {
+            Filter::Lowpass(fc) => butterworth(Pass::Low, check(fc)?, fs, order),
+            Filter::Highpass(fc) => butterworth(Pass::High, check(fc)?, fs, order),
+            Filter::Bandpass(low, high) => {
+                let mut sections = butterworth(Pass::High, check(low)?, fs, order);
+                sections.extend(butterworth(Pass::Low, check(high)?, fs, order));
+                sections
+            }
+            Filter::Notch(f0) => vec![Section::notch(check(f0)?, fs, NOTCH_Q)],
+            Filter::MovingAverage(_) => unreachable!("Not an IIR filter"),
+        };
+        Ok(sections)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Pass {
+    Low,
+    High,
+}
+
+/// Second-order IIR section, with `a0` normalized to 1
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Section {
+    /// Second-order section of the bilinear transform with pre-warping, from the audio EQ cookbook
+    fn second_order(pass: Pass, fc: f64, fs: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * fc / fs;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        let b = match pass {
+            Pass::Low => [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            Pass::High => [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+        };
+        Self {
+            b: b.map(|b| b / a0),
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        }
+    }
+
+    /// First-order section for odd filter orders
+    fn first_order(pass: Pass, fc: f64, fs: f64) -> Self {
+        let k = (PI * fc / fs).tan();
+        let b = match pass {
+            Pass::Low => [k / (1.0 + k), k / (1.0 + k), 0.0],
+            Pass::High => [1.0 / (1.0 + k), -1.0 / (1.0 + k), 0.0],
+        };
+        Self {
+            b,
+            a: [(k - 1.0) / (k + 1.0), 0.0],
+        }
+    }
+
+    fn notch(f0: f64, fs: f64, q: f64) -> Self {
+        let w0 = 2.0 * PI * f0 / fs;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        Self {
+            b: [1.0 / a0, -2.0 * cos / a0, 1.0 / a0],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        }
+    }
+
+    fn dc_gain(&self) -> f64 {
+        self.b.iter().sum::<f64>() / (1.0 + self.a[0] + self.a[1])
+    }
+
+    /// State of the transposed direct form II in steady state for a constant input of `x`
+    fn steady_state(&self, x: f64) -> [f64; 2] {
+        let y = self.dc_gain() * x;
+        [y - self.b[0] * x, self.b[2] * x - self.a[1] * y]
+    }
+}
+
+/// Butterworth filter as a cascade of sections, the poles of each pair give the Q of a section
+fn butterworth(pass: Pass, fc: f64, fs: f64, order: usize) -> Vec<Section> {
+    let n = order as f64;
+    let mut sections: Vec<Section> = (0..order / 2)
+        .map(|k| {
+            let q = 1.0 / (2.0 * ((2 * k + 1) as f64 * PI / (2.0 * n)).sin());
+            Section::second_order(pass, fc, fs, q)
+        })
+        .collect();
+    if order % 2 == 1 {
+        sections.push(Section::first_order(pass, fc, fs));
+    }
+    sections
+}
+
+/// Filter in place with the cascade, starting each section in the steady state of the first value
+fn filter_sections(sections: &[Section], values: &mut [f64]) {
+    let Some(&first) = values.first() else {
+        return;
+    };
+    let mut level = first;
+    for section in sections {
+        let [mut z1, mut z2] = section.steady_state(level);
+        level *= section.dc_gain();
+        for x in values.iter_mut() {
+            let y = section.b[0] * *x + z1;
+            z1 = section.b[1] * *x - section.a[0] * y + z2;
+            z2 = section.b[2] * *x - section.a[1] * y;
+            *x = y;
+        }
+    }
+}
+
+/// Zero-phase filtering, the values are extended at both ends by odd reflection to reduce
+/// the transients at the edges
+fn filtfilt(sections: &[Section], values: &[f64]) -> Vec<f64> {
+    let len = values.len();
+    if len < 2 {
+        return values.to_vec();
+    }
+    let pad = (3 * (2 * sections.len() + 1)).min(len - 1);
+    let (first, last) = (values[0], values[len - 1]);
+
+    let mut extended = Vec::with_capacity(len + 2 * pad);
+    extended.extend((1..=pad).rev().map(|i| 2.0 * first - values[i]));
+    extended.extend_from_slice(values);
+    extended.extend((1..=pad).map(|i| 2.0 * last - values[len - 1 - i]));
+
+    filter_sections(sections, &mut extended);
+    extended.reverse();
+    filter_sections(sections, &mut extended);
+    extended.reverse();
+
+    extended[pad..pad + len].to_vec()
+}
+
+/// Centered moving average, NaN and infinite values are left out of the windows and stay NaN
+fn moving_average(values: &[f64], window: usize) -> Vec<f64> {
+    // Prefix sums of the finite values and their count
+    let mut sums = Vec::with_capacity(values.len() + 1);
+    let mut counts = Vec::with_capacity(values.len() + 1);
+    let (mut sum, mut count) = (0.0, 0usize);
+    sums.push(sum);
+    counts.push(count);
+    for &x in values {
+        if x.is_finite() {
+            sum += x;
+            count += 1;
+        }
+        sums.push(sum);
+        counts.push(count);
+    }
+
+    let before = (window - 1) / 2;
+    let after = window / 2;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            if !x.is_finite() {
+                return f64::NAN;
+            }
+            let start = i.saturating_sub(before);
+            let end = (i + after + 1).min(values.len());
+            (sums[end] - sums[start]) / (counts[end] - counts[start]) as f64
+        })
+        .collect()
+}
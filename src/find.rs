@@ -0,0 +1,102 @@
+//! Search for datasets by path, type, shape and attributes, without reading any data
+
+use std::io::Write;
+
+use anyhow::bail;
+use serde_json::Value;
+
+use crate::{
+    config::{Config, FindArgs},
+    my_hdf5::{
+        inspect::{DatasetInfo, GroupInfo},
+        util::NativePrimitiveType,
+    },
+};
+
+/// An attribute that matching datasets must have, with the given value if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttrMatch {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// Parse an attribute to match, e.g. `units=V`, or `units` for any value
+pub fn parse_attr_match(s: &str) -> anyhow::Result<AttrMatch> {
+    let (name, value) = match s.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_owned())),
+        None => (s, None),
+    };
+    if name.is_empty() {
+        bail!("Invalid attribute '{s}', expected e.g. 'units=V' or 'units'");
+    }
+    Ok(AttrMatch {
+        name: name.to_owned(),
+        value,
+    })
+}
+
+impl AttrMatch {
+    fn matches(&self, dataset: &DatasetInfo) -> bool {
+        let Some(attr) = dataset.attributes.iter().find(|a| a.name == self.name) else {
+            return false;
+        };
+        let Some(expected) = &self.value else {
+            return true;
+        };
+        match &attr.value {
+            Value::String(s) => s == expected,
+            Value::Number(n) => match (n.as_f64(), expected.parse::<f64>()) {
+                (Some(value), Ok(expected)) => value == expected,
+                _ => false,
+            },
+            Value::Bool(b) => expected
+                .parse::<bool>()
+                .is_ok_and(|expected| *b == expected),
+            _ => false,
+        }
+    }
+}
+
+pub fn handle_find_cmd(args: &FindArgs, _cfg: &Config) -> anyhow::Result<()> {
+    log::trace!("{args:?}");
+    log::debug!("opening: {:?}", args.src_hdf5.as_path());
+    let file = hdf5::File::open(args.src_hdf5.as_path())?;
+
+    let root = GroupInfo::read(&file)?;
+    let mut stdout = std::io::stdout().lock();
+    let mut found = 0;
+    for dataset in root.datasets().into_iter().filter(|d| matches(args, d)) {
+        // Compared by type rather than by name, as pointer-sized types have several names
+        if let Some(dtype) = args.dtype {
+            let native =
+                NativePrimitiveType::try_from_dtype(&file.dataset(&dataset.path)?.dtype()?);
+            if native != Some(dtype) {
+                continue;
+            }
+        }
+        writeln!(stdout, "{}", dataset.path)?;
+        found += 1;
+    }
+    log::debug!("{found} datasets match");
+    Ok(())
+}
+
+/// Whether the dataset matches all of the criteria but its type
+fn matches(args: &FindArgs, dataset: &DatasetInfo) -> bool {
+    if args
+        .name
+        .as_ref()
+        .is_some_and(|name| !name.is_match(&dataset.path))
+    {
+        return false;
+    }
+    if args.ndim.is_some_and(|ndim| dataset.shape.len() != ndim) {
+        return false;
+    }
+    // Scalars have a single sample
+    let len = dataset.shape.first().copied().unwrap_or(1);
+    if args.min_len.is_some_and(|min_len| len < min_len) {
+        return false;
+    }
+    args.attrs.iter().all(|attr| attr.matches(dataset))
+}
@@ -1,17 +1,73 @@
 use crate::{
-    config::{Config, InspectArgs},
-    my_hdf5::inspect::print_group_info,
+    config::{misc::InspectFormat, Config, InspectArgs},
+    my_hdf5::{
+        file::{open_swmr_reader, FileMetadata},
+        inspect::{
+            print_attributes_only, print_dataset_info, print_datasets_by_size, print_file_metadata,
+            print_group_info, print_tree, DatasetInfo, FileInfo, GroupInfo, LinkOptions,
+            PathFilter,
+        },
+    },
     util::print_colored_quoted,
 };
+use std::io::Write;
+
+use anyhow::bail;
+use hdf5::LocationType;
 use termcolor::{Color, StandardStream};
 
 pub fn handle_inspect(args: &InspectArgs, cfg: &Config) -> anyhow::Result<()> {
     log::trace!("{args:?}");
+    let filter = PathFilter {
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+        max_depth: args.max_depth,
+    };
 
     log::debug!("opening: {:?}", args.src_hdf5.as_path());
-    let file = hdf5::File::open(args.src_hdf5.as_path())?;
+    let file = match hdf5::File::open(args.src_hdf5.as_path()) {
+        Ok(file) => file,
+        Err(e) => match open_swmr_reader(&args.src_hdf5) {
+            Ok(file) => {
+                log::warn!(
+                    "Opened {0} as a SWMR reader, it is open for SWMR writing or its writer \
+                     exited without closing it. Once no writer has it open, `h5clear -s {0}` \
+                     clears its status",
+                    args.src_hdf5.display()
+                );
+                file
+            }
+            Err(_) => return Err(e.into()),
+        },
+    };
 
     let mut stdout = StandardStream::stdout(cfg.color_when());
+    let path = args.path.as_deref().unwrap_or("/");
+    let group = match file.loc_type_by_name(path)? {
+        LocationType::Group => file.group(path)?,
+        LocationType::Dataset if args.tree || args.attrs_only || args.sort_by_size => {
+            bail!("{path} is a dataset, expected a group")
+        }
+        LocationType::Dataset if args.format == InspectFormat::Json => {
+            let info = DatasetInfo::read(&file.dataset(path)?)?;
+            serde_json::to_writer_pretty(&mut stdout, &info)?;
+            writeln!(stdout)?;
+            return Ok(());
+        }
+        LocationType::Dataset => {
+            let dataset = file.dataset(path)?;
+            return print_dataset_info(&dataset, args.preview_samples, &mut stdout);
+        }
+        LocationType::NamedDatatype => bail!("{path} is a named datatype, not a group"),
+    };
+
+    if args.format == InspectFormat::Json {
+        let info = FileInfo::read(&file, &group, &filter)?;
+        serde_json::to_writer_pretty(&mut stdout, &info)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
+
     print_colored_quoted(
         &mut stdout,
         Color::Green,
@@ -19,7 +75,24 @@ pub fn handle_inspect(args: &InspectArgs, cfg: &Config) -> anyhow::Result<()> {
         format!("{file:?}"),
     )?;
 
-    print_group_info(&file, args.preview_samples, &mut stdout)?;
+    if args.tree {
+        print_tree(&GroupInfo::read_filtered(&group, &filter)?, &mut stdout)?;
+    } else if args.sort_by_size {
+        print_datasets_by_size(&GroupInfo::read_filtered(&group, &filter)?, &mut stdout)?;
+    } else if args.attrs_only {
+        print_attributes_only(
+            &GroupInfo::read_filtered(&group, &filter)?,
+            args.preview_samples,
+            &mut stdout,
+        )?;
+    } else {
+        print_file_metadata(&FileMetadata::read(&file)?, &mut stdout)?;
+        let links = LinkOptions {
+            follow_external: args.follow_external,
+            report_dangling: args.dangling,
+        };
+        print_group_info(&group, args.preview_samples, links, &filter, &mut stdout)?;
+    }
 
     Ok(())
 }
@@ -1,7 +1,16 @@
+pub mod checksum;
 pub mod config;
+pub mod correlate;
+pub mod diff;
+pub mod events;
+pub mod expr;
+pub mod filter;
+pub mod find;
 pub mod inspect;
 pub mod my_hdf5;
 pub mod plot;
+pub mod resample;
+pub mod stats;
 pub mod swmr;
 
 pub mod util;
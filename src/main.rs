@@ -1,7 +1,13 @@
 use hdf5_test::{
+    checksum::handle_checksum_cmd,
     config::{Command, Config},
+    correlate::handle_correlate_cmd,
+    diff::handle_diff_cmd,
+    events::handle_events_cmd,
+    find::handle_find_cmd,
     inspect::handle_inspect,
     plot::handle_plot_cmd,
+    stats::handle_stats_cmd,
 };
 
 fn main() -> anyhow::Result<()> {
@@ -11,8 +17,14 @@ fn main() -> anyhow::Result<()> {
     match cfg.command {
         Some(ref c) => match c {
             Command::Plot(ref args) => handle_plot_cmd(args, &cfg)?,
+            Command::Stats(ref args) => handle_stats_cmd(args, &cfg)?,
+            Command::Events(ref args) => handle_events_cmd(args, &cfg)?,
+            Command::Correlate(ref args) => handle_correlate_cmd(args, &cfg)?,
             Command::TestSwMr => hdf5_test::swmr::multiple_reader()?,
             Command::Inspect(ref args) => handle_inspect(args, &cfg)?,
+            Command::Find(ref args) => handle_find_cmd(args, &cfg)?,
+            Command::Diff(ref args) => handle_diff_cmd(args, &cfg)?,
+            Command::Checksum(ref args) => handle_checksum_cmd(args, &cfg)?,
         },
         None => log::trace!("No subcommand"),
     }
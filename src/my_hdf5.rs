@@ -1,5 +1,11 @@
+pub mod attribute;
+pub mod cf;
+pub mod chunked;
 pub mod dataset;
 pub mod dataspace;
+pub mod file;
 pub mod htype;
 pub mod inspect;
+pub mod link;
+pub mod storage;
 pub mod util;
@@ -0,0 +1,157 @@
+//! Values of attributes of any supported type, as JSON values
+//!
+//! Numbers, booleans and strings are read with the high-level API. Enums are read as the names
+//! of their members, and fixed-size arrays and compound fields are converted by the library to
+//! native numbers, so they are read with the memory types built here.
+
+use std::{ffi::CString, mem::size_of};
+
+use hdf5::{
+    h5call,
+    types::{FixedAscii, FixedUnicode, TypeDescriptor, VarLenAscii, VarLenUnicode},
+    Attribute, Datatype, H5Type,
+};
+use hdf5_sys::{
+    h5::hsize_t,
+    h5a::H5Aread,
+    h5i::hid_t,
+    h5t::{H5T_class_t, H5Tarray_create2, H5Tclose, H5Tcreate, H5Tinsert},
+};
+use serde_json::{Map, Value};
+
+/// Longest fixed-length string read from attributes, longer strings are truncated
+const MAX_STRING_LEN: usize = 1024;
+
+/// Read the value of the attribute, a single value for scalars and a list of values otherwise,
+/// `null` if the type is not supported
+pub fn read_value(attr: &Attribute) -> anyhow::Result<Value> {
+    let descriptor = attr.dtype()?.to_descriptor()?;
+    let Some(values) = read_elements(attr, &descriptor)? else {
+        return Ok(Value::Null);
+    };
+    Ok(match attr.is_scalar() {
+        true => values.into_iter().next().unwrap_or(Value::Null),
+        false => Value::Array(values),
+    })
+}
+
+fn read_elements(
+    attr: &Attribute,
+    descriptor: &TypeDescriptor,
+) -> anyhow::Result<Option<Vec<Value>>> {
+    let count = attr.size();
+    let values = match descriptor {
+        TypeDescriptor::Integer(_) => to_values(attr.read_raw::<i64>()?),
+        TypeDescriptor::Unsigned(_) => to_values(attr.read_raw::<u64>()?),
+        TypeDescriptor::Float(_) => to_values(attr.read_raw::<f64>()?),
+        TypeDescriptor::Boolean => to_values(attr.read_raw::<bool>()?),
+        TypeDescriptor::VarLenUnicode => strings(attr.read_raw::<VarLenUnicode>()?, |s| s.as_str()),
+        TypeDescriptor::VarLenAscii => strings(attr.read_raw::<VarLenAscii>()?, |s| s.as_str()),
+        TypeDescriptor::FixedUnicode(_) => {
+            strings(attr.read_raw::<FixedUnicode<MAX_STRING_LEN>>()?, |s| {
+                s.as_str()
+            })
+        }
+        TypeDescriptor::FixedAscii(_) => {
+            strings(attr.read_raw::<FixedAscii<MAX_STRING_LEN>>()?, |s| {
+                s.as_str()
+            })
+        }
+        TypeDescriptor::Enum(enum_type) => {
+            let mem_type = Datatype::from_type::<i64>()?;
+            read_converted::<i64>(attr, mem_type.id(), count)?
+                .into_iter()
+                .map(|value| {
+                    // Members store the bit pattern of signed values
+                    match enum_type.members.iter().find(|m| m.value == value as u64) {
+                        Some(member) => Value::from(member.name.as_str()),
+                        None => Value::from(value),
+                    }
+                })
+                .collect()
+        }
+        TypeDescriptor::FixedArray(element, len) => match **element {
+            TypeDescriptor::Integer(_) => read_arrays::<i64>(attr, *len, count)?,
+            TypeDescriptor::Unsigned(_) => read_arrays::<u64>(attr, *len, count)?,
+            TypeDescriptor::Float(_) => read_arrays::<f64>(attr, *len, count)?,
+            _ => return Ok(None),
+        },
+        TypeDescriptor::Compound(compound) => {
+            let mut rows = vec![Map::new(); count];
+            for field in &compound.fields {
+                let column = match field.ty {
+                    TypeDescriptor::Integer(_) => {
+                        to_values(read_field::<i64>(attr, &field.name, count)?)
+                    }
+                    TypeDescriptor::Unsigned(_) => {
+                        to_values(read_field::<u64>(attr, &field.name, count)?)
+                    }
+                    TypeDescriptor::Float(_) => {
+                        to_values(read_field::<f64>(attr, &field.name, count)?)
+                    }
+                    TypeDescriptor::Boolean => {
+                        to_values(read_field::<bool>(attr, &field.name, count)?)
+                    }
+                    _ => vec![Value::Null; count],
+                };
+                for (row, value) in rows.iter_mut().zip(column) {
+                    row.insert(field.name.clone(), value);
+                }
+            }
+            rows.into_iter().map(Value::Object).collect()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(values))
+}
+
+fn to_values<T: Into<Value>>(values: Vec<T>) -> Vec<Value> {
+    values.into_iter().map(Into::into).collect()
+}
+
+fn strings<T>(values: Vec<T>, as_str: impl Fn(&T) -> &str) -> Vec<Value> {
+    values.iter().map(|s| Value::from(as_str(s))).collect()
+}
+
+/// Read `count` elements of the attribute, converted by the library to the memory type
+fn read_converted<T: Copy + Default>(
+    attr: &Attribute,
+    mem_type: hid_t,
+    count: usize,
+) -> anyhow::Result<Vec<T>> {
+    let mut values = vec![T::default(); count];
+    h5call!(H5Aread(attr.id(), mem_type, values.as_mut_ptr().cast()))?;
+    Ok(values)
+}
+
+/// Read the elements of an attribute of fixed-size arrays of `len` numbers
+fn read_arrays<T>(attr: &Attribute, len: usize, count: usize) -> anyhow::Result<Vec<Value>>
+where
+    T: H5Type + Copy + Default + Into<Value>,
+{
+    let element = Datatype::from_type::<T>()?;
+    let dims = [len as hsize_t];
+    let mem_type = h5call!(H5Tarray_create2(element.id(), 1, dims.as_ptr()))?;
+    let values = read_converted::<T>(attr, mem_type, count * len);
+    h5call!(H5Tclose(mem_type))?;
+
+    Ok(values?
+        .chunks(len.max(1))
+        .map(|array| Value::Array(array.iter().map(|&x| x.into()).collect()))
+        .collect())
+}
+
+/// Read one field of a compound attribute, the library matches the field by its name
+fn read_field<T>(attr: &Attribute, field: &str, count: usize) -> anyhow::Result<Vec<T>>
+where
+    T: H5Type + Copy + Default,
+{
+    let member = Datatype::from_type::<T>()?;
+    let c_name = CString::new(field)?;
+    let mem_type = h5call!(H5Tcreate(H5T_class_t::H5T_COMPOUND, size_of::<T>()))?;
+    let values = h5call!(H5Tinsert(mem_type, c_name.as_ptr(), 0, member.id()))
+        .map_err(anyhow::Error::from)
+        .and_then(|_| read_converted::<T>(attr, mem_type, count));
+    h5call!(H5Tclose(mem_type))?;
+    values
+}
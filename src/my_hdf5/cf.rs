@@ -0,0 +1,163 @@
+//! Packing attributes of the CF conventions, as written by netCDF and h5py based tools
+//!
+//! Packed datasets store e.g. 16-bit counts together with `scale_factor` and `add_offset`
+//! attributes, the physical value is `packed * scale_factor + add_offset`. Values equal to
+//! `_FillValue` or `missing_value`, and values outside of `valid_range` (or `valid_min` and
+//! `valid_max`) mark missing data.
+
+use anyhow::Context;
+use hdf5::{types::TypeDescriptor, Attribute, Dataset};
+use num_traits::ToPrimitive;
+
+use super::{
+    chunked::Sample,
+    util::{type_name, NativePrimitiveType},
+};
+
+/// How the values of a dataset are unpacked and masked
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packing {
+    pub scale_factor: f64,
+    pub add_offset: f64,
+    /// Packed values marking missing data, from `_FillValue` and `missing_value`
+    pub missing: Vec<f64>,
+    /// Smallest valid packed value
+    pub valid_min: Option<f64>,
+    /// Largest valid packed value
+    pub valid_max: Option<f64>,
+    /// Type of the unpacked values, the type of `scale_factor` or `add_offset` if present
+    pub unpacked_type: NativePrimitiveType,
+}
+
+impl Packing {
+    /// Read the packing attributes of the dataset, `None` if it has none of them
+    pub fn from_dataset(dataset: &Dataset) -> anyhow::Result<Option<Self>> {
+        let names = dataset.attr_names()?;
+        // Attributes that are not numeric, e.g. a `_FillValue` written as a string, are ignored
+        let attr = |name: &str| -> anyhow::Result<Option<Attribute>> {
+            if !names.iter().any(|n| n == name) {
+                return Ok(None);
+            }
+            let attr = dataset.attr(name)?;
+            let dtype = attr.dtype()?;
+            if NativePrimitiveType::try_from_dtype(&dtype).is_none() {
+                log::warn!(
+                    "Ignoring {name} of {}, its type {} is not numeric",
+                    dataset.name(),
+                    type_name(&dtype)
+                );
+                return Ok(None);
+            }
+            Ok(Some(attr))
+        };
+        let first_value = |attr: &Attribute| -> anyhow::Result<Option<f64>> {
+            Ok(attr.read_raw::<f64>()?.first().copied())
+        };
+
+        let scale_factor = attr("scale_factor")?;
+        let add_offset = attr("add_offset")?;
+        let fill_value = attr("_FillValue")?;
+        let missing_value = attr("missing_value")?;
+        let valid_range = attr("valid_range")?;
+        let valid_min = attr("valid_min")?;
+        let valid_max = attr("valid_max")?;
+        if [
+            &scale_factor,
+            &add_offset,
+            &fill_value,
+            &missing_value,
+            &valid_range,
+            &valid_min,
+            &valid_max,
+        ]
+        .iter()
+        .all(|a| a.is_none())
+        {
+            return Ok(None);
+        }
+
+        let mut packing = Self {
+            scale_factor: 1.0,
+            add_offset: 0.0,
+            missing: vec![],
+            valid_min: None,
+            valid_max: None,
+            unpacked_type: NativePrimitiveType::try_from_dtype(&dataset.dtype()?)
+                .with_context(|| format!("The packed dataset {} is not numeric", dataset.name()))?,
+        };
+        for (attr, value) in [
+            (&scale_factor, &mut packing.scale_factor),
+            (&add_offset, &mut packing.add_offset),
+        ] {
+            if let Some(attr) = attr {
+                *value = first_value(attr)?.unwrap_or(*value);
+                if let Some(native) = NativePrimitiveType::try_from_dtype(&attr.dtype()?) {
+                    packing.unpacked_type = native;
+                }
+            }
+        }
+        for attr in [&fill_value, &missing_value].into_iter().flatten() {
+            packing.missing.extend(attr.read_raw::<f64>()?);
+        }
+
+        // The valid range is given in packed units, unless it has the type of the unpacked
+        // values while the dataset holds packed integers
+        let (mut min, mut max) = match &valid_range {
+            Some(attr) => match attr.read_raw::<f64>()?[..] {
+                [min, max] => (Some(min), Some(max)),
+                _ => {
+                    log::warn!(
+                        "Ignoring valid_range of {}, expected 2 values",
+                        dataset.name()
+                    );
+                    (None, None)
+                }
+            },
+            None => (None, None),
+        };
+        if let Some(attr) = &valid_min {
+            min = first_value(attr)?;
+        }
+        if let Some(attr) = &valid_max {
+            max = first_value(attr)?;
+        }
+        let range_attr = valid_range
+            .as_ref()
+            .or(valid_min.as_ref())
+            .or(valid_max.as_ref());
+        if let Some(attr) = range_attr {
+            let is_float = |desc: TypeDescriptor| matches!(desc, TypeDescriptor::Float(_));
+            if is_float(attr.dtype()?.to_descriptor()?)
+                && !is_float(dataset.dtype()?.to_descriptor()?)
+            {
+                min = min.map(|min| packing.pack(min));
+                max = max.map(|max| packing.pack(max));
+            }
+        }
+        packing.valid_min = min;
+        packing.valid_max = max;
+
+        log::debug!("Unpacking {}: {packing:?}", dataset.name());
+        Ok(Some(packing))
+    }
+
+    /// The physical value of a packed value, NaN if it marks missing data
+    pub fn unpack(&self, packed: f64) -> f64 {
+        let masked = self.missing.contains(&packed)
+            || self.valid_min.is_some_and(|min| packed < min)
+            || self.valid_max.is_some_and(|max| packed > max);
+        if masked {
+            f64::NAN
+        } else {
+            packed * self.scale_factor + self.add_offset
+        }
+    }
+
+    pub fn unpack_sample<T: Sample>(&self, sample: T) -> f64 {
+        self.unpack(sample.widen().to_f64().unwrap_or(f64::NAN))
+    }
+
+    fn pack(&self, value: f64) -> f64 {
+        (value - self.add_offset) / self.scale_factor
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    marker::PhantomData,
+    mem::size_of,
+    ops::{Add, Sub},
+};
+
+use anyhow::bail;
+use hdf5::{Dataset, H5Type, Hyperslab, Selection, SliceOrIndex};
+use ndarray::{Array1, ArrayD, Ix2};
+use num_traits::{ToPrimitive, Zero};
+use serde::Serialize;
+
+use super::{
+    cf::Packing,
+    util::{type_name, with_native_type, NativePrimitiveType},
+};
+
+/// Element types of datasets that can be read as samples
+pub trait Sample: H5Type + Copy {
+    /// Type that samples are folded in, wide enough that the sums do not overflow
+    type Wide: Copy
+        + Zero
+        + Add<Output = Self::Wide>
+        + Sub<Output = Self::Wide>
+        + ToPrimitive
+        + Serialize
+        + 'static;
+
+    fn widen(self) -> Self::Wide;
+}
+
+macro_rules! impl_sample {
+    ($wide:ty: $($t:ty),*) => {
+        $(
+            impl Sample for $t {
+                type Wide = $wide;
+
+                fn widen(self) -> $wide {
+                    self as $wide
+                }
+            }
+        )*
+    };
+}
+
+impl_sample!(i128: i8, i16, i32, i64, u8, u16, u32, u64, usize);
+impl_sample!(f64: f32, f64);
+
+/// Iterator over blocks of whole rows (along axis 0) of a dataset
+///
+/// The number of rows in a block is chosen so that a block fits within the memory budget, and
+/// for chunked datasets it is rounded down to a multiple of the chunk size along axis 0, so that
+/// each chunk is read (and decompressed) only once.
+pub struct RowBlocks<'a, T> {
+    dataset: &'a Dataset,
+    ndim: usize,
+    rows: usize,
+    rows_per_block: usize,
+    next_row: usize,
+    _elem: PhantomData<T>,
+}
+
+impl<'a, T: H5Type> RowBlocks<'a, T> {
+    pub fn new(dataset: &'a Dataset, memory_budget: usize) -> Self {
+        let shape = dataset.shape();
+        let rows = shape.first().copied().unwrap_or(0);
+        let row_bytes = shape.iter().skip(1).product::<usize>() * size_of::<T>();
+        let mut rows_per_block = (memory_budget / row_bytes.max(1)).max(1);
+
+        if let Some(&chunk_rows) = dataset.chunk().as_ref().and_then(|c| c.first()) {
+            if chunk_rows > 0 && rows_per_block >= chunk_rows {
+                rows_per_block -= rows_per_block % chunk_rows;
+            }
+        }
+        log::debug!(
+            "Reading {} in blocks of {rows_per_block} rows",
+            dataset.name()
+        );
+
+        Self {
+            dataset,
+            ndim: shape.len(),
+            rows,
+            rows_per_block,
+            next_row: 0,
+            _elem: PhantomData,
+        }
+    }
+}
+
+impl<T: H5Type> Iterator for RowBlocks<'_, T> {
+    /// The index of the first row in the block and the block itself
+    type Item = hdf5::Result<(usize, ArrayD<T>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_row >= self.rows {
+            return None;
+        }
+        let start = self.next_row;
+        let end = (start + self.rows_per_block).min(self.rows);
+        self.next_row = end;
+
+        let mut slices = vec![SliceOrIndex::from(start..end)];
+        slices.resize(self.ndim, SliceOrIndex::from(..));
+        let selection = Selection::from(Hyperslab::from(slices));
+
+        Some(
+            self.dataset
+                .read_slice::<T, _, _>(selection)
+                .map(|block| (start, block)),
+        )
+    }
+}
+
+/// The elements of a dataset in row-major order, read in blocks of at most `memory_budget`
+/// bytes
+pub fn elements<T: H5Type + Copy>(
+    dataset: &Dataset,
+    memory_budget: usize,
+) -> impl Iterator<Item = hdf5::Result<T>> + '_ {
+    RowBlocks::<T>::new(dataset, memory_budget).flat_map(|block| {
+        let (values, err) = match block {
+            Ok((_, block)) => (block.into_raw_vec_and_offset().0, None),
+            Err(e) => (vec![], Some(Err(e))),
+        };
+        values.into_iter().map(Ok).chain(err)
+    })
+}
+
+/// Visit each sample of a 1-D dataset, or of a 2-D dataset folded (summed) along `axis`,
+/// while reading at most `memory_budget` bytes of the dataset at a time
+///
+/// Samples are widened to [`Sample::Wide`] before folding, so integer sums cannot overflow.
+/// Stops at the first error returned by `f`
+pub fn for_each_sample<T, F>(
+    dataset: &Dataset,
+    axis: usize,
+    memory_budget: usize,
+    f: F,
+) -> anyhow::Result<()>
+where
+    T: Sample,
+    F: FnMut(usize, T::Wide) -> anyhow::Result<()>,
+{
+    for_each_mapped::<T, _, _, _>(dataset, axis, memory_budget, T::widen, f)
+}
+
+/// Number of samples that [`for_each_sample`] visits
+pub fn sample_count(dataset: &Dataset, axis: usize) -> anyhow::Result<usize> {
+    let shape = dataset.shape();
+    match (shape.len(), axis) {
+        (1, _) | (2, 1) => Ok(shape[0]),
+        (2, 0) => Ok(shape[1]),
+        (2, _) => bail!("Invalid axis {axis} for a 2-D dataset"),
+        (ndims, _) => bail!("Unsupported dataset dimensionality: {ndims}"),
+    }
+}
+
+/// Like [`for_each_sample`], but each element is converted with `map` before folding,
+/// e.g. to unpack it
+pub fn for_each_mapped<T, W, M, F>(
+    dataset: &Dataset,
+    axis: usize,
+    memory_budget: usize,
+    map: M,
+    mut f: F,
+) -> anyhow::Result<()>
+where
+    T: H5Type + Copy,
+    W: Copy + Zero + Add<Output = W>,
+    M: Fn(T) -> W,
+    F: FnMut(usize, W) -> anyhow::Result<()>,
+{
+    let ndims = dataset.ndim();
+
+    match (ndims, axis) {
+        (1, _) => {
+            for block in RowBlocks::<T>::new(dataset, memory_budget) {
+                let (start, block) = block?;
+                for (i, &x) in block.iter().enumerate() {
+                    f(start + i, map(x))?;
+                }
+            }
+        }
+        (2, 0) => {
+            // Every row contributes to every sample, so the sums are accumulated over all blocks
+            let mut sums: Array1<W> = Array1::zeros(dataset.shape()[1]);
+            for block in RowBlocks::<T>::new(dataset, memory_budget) {
+                let (_, block) = block?;
+                for row in block.into_dimensionality::<Ix2>()?.rows() {
+                    for (sum, &x) in sums.iter_mut().zip(row) {
+                        *sum = *sum + map(x);
+                    }
+                }
+            }
+            for (i, &x) in sums.iter().enumerate() {
+                f(i, x)?;
+            }
+        }
+        (2, 1) => {
+            for block in RowBlocks::<T>::new(dataset, memory_budget) {
+                let (start, block) = block?;
+                for (i, row) in block
+                    .into_dimensionality::<Ix2>()?
+                    .rows()
+                    .into_iter()
+                    .enumerate()
+                {
+                    let sum = row.iter().fold(W::zero(), |acc, &x| acc + map(x));
+                    f(start + i, sum)?;
+                }
+            }
+        }
+        (2, _) => bail!("Invalid axis {axis} for a 2-D dataset"),
+        _ => bail!("Unsupported dataset dimensionality: {ndims}"),
+    }
+
+    Ok(())
+}
+
+/// Read the samples of a 1-D dataset, or of a 2-D dataset folded along `axis`, as floats,
+/// unpacking them if the dataset is packed
+pub fn read_samples(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    axis: usize,
+    memory_budget: usize,
+) -> anyhow::Result<Vec<f64>> {
+    let dtype = dataset.dtype()?;
+    let Some(native) = NativePrimitiveType::try_from_dtype(&dtype) else {
+        bail!(
+            "{} is not numeric, its type is {}",
+            dataset.name(),
+            type_name(&dtype)
+        );
+    };
+    with_native_type!(
+        native,
+        T,
+        read_samples_as::<T>(dataset, packing, axis, memory_budget)
+    )
+}
+
+fn read_samples_as<T: Sample>(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    axis: usize,
+    memory_budget: usize,
+) -> anyhow::Result<Vec<f64>> {
+    let mut samples = vec![];
+    match packing {
+        Some(packing) => for_each_mapped::<T, _, _, _>(
+            dataset,
+            axis,
+            memory_budget,
+            |x| packing.unpack_sample(x),
+            |_, x| {
+                samples.push(x);
+                Ok(())
+            },
+        )?,
+        None => for_each_sample::<T, _>(dataset, axis, memory_budget, |_, x| {
+            samples.push(x.to_f64().unwrap_or(f64::NAN));
+            Ok(())
+        })?,
+    }
+    Ok(samples)
+}
@@ -0,0 +1,160 @@
+//! Metadata of a file as a whole: its size, format versions, free space, driver and status
+
+use std::{
+    ffi::{c_uint, CString},
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::Context;
+use hdf5::h5call;
+use hdf5_sys::{
+    h5::hsize_t,
+    h5f::{
+        H5F_info2_t, H5F_libver_t, H5Fget_access_plist, H5Fget_create_plist, H5Fget_filesize,
+        H5Fget_freespace, H5Fget_info2, H5Fget_intent, H5Fopen, H5F_ACC_RDONLY, H5F_ACC_SWMR_READ,
+    },
+    h5i::hid_t,
+    h5p::{H5Pclose, H5Pget_libver_bounds, H5Pget_userblock},
+};
+use serde::Serialize;
+
+use crate::swmr::SwmrReadFapl;
+
+/// Flag of the file consistency flags, set while a writer has the file open
+const SUPER_WRITE_ACCESS: u8 = 0x01;
+/// Flag of the file consistency flags, set while a SWMR writer has the file open
+const SUPER_SWMR_WRITE_ACCESS: u8 = 0x04;
+/// Offset of the file consistency flags in superblocks of version 2 and 3
+const STATUS_FLAGS_OFFSET: u64 = 11;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileMetadata {
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Unused space in the file in bytes, which `h5repack` would reclaim
+    pub free_space: u64,
+    pub superblock_version: u32,
+    /// Bytes before the superblock reserved for the user, e.g. for a header of another format
+    pub userblock_size: u64,
+    /// Earliest library version that objects written to the file as opened must be readable
+    /// by, e.g. `earliest` or `v110`
+    pub libver_low: String,
+    /// Latest library version whose object formats may be used when writing
+    pub libver_high: String,
+    /// Virtual file driver the file is opened with, e.g. `Sec2`
+    pub driver: String,
+    /// File consistency flags of the superblock, read from the file, `null` if the superblock
+    /// version does not record them or they could not be read
+    pub status_flags: Option<u8>,
+    /// Whether the file is opened as a SWMR reader
+    pub swmr_reader: bool,
+}
+
+impl FileMetadata {
+    pub fn read(file: &hdf5::File) -> anyhow::Result<Self> {
+        let mut size: hsize_t = 0;
+        h5call!(H5Fget_filesize(file.id(), &mut size))?;
+        let free_space = h5call!(H5Fget_freespace(file.id()))?;
+        let mut info = H5F_info2_t::default();
+        h5call!(H5Fget_info2(file.id(), &mut info))?;
+
+        let create_plist = h5call!(H5Fget_create_plist(file.id()))?;
+        let userblock_size = read_userblock(create_plist);
+        h5call!(H5Pclose(create_plist))?;
+        let access_plist = h5call!(H5Fget_access_plist(file.id()))?;
+        let libver_bounds = read_libver_bounds(access_plist);
+        h5call!(H5Pclose(access_plist))?;
+        let (libver_low, libver_high) = libver_bounds?;
+        let userblock_size = userblock_size?;
+        let mut intent: c_uint = 0;
+        h5call!(H5Fget_intent(file.id(), &mut intent))?;
+
+        // The library does not report them, they are read from the superblock itself
+        let status_flags = match info.super_.version >= 2 {
+            true => read_status_flags(&file.filename(), userblock_size)
+                .map_err(|e| log::warn!("Failed reading the status of the file: {e}"))
+                .ok(),
+            false => None,
+        };
+
+        Ok(Self {
+            size,
+            free_space: free_space as u64,
+            superblock_version: info.super_.version,
+            userblock_size,
+            libver_low,
+            libver_high,
+            driver: format!("{:?}", file.access_plist()?.get_driver()?),
+            status_flags,
+            swmr_reader: intent & H5F_ACC_SWMR_READ != 0,
+        })
+    }
+
+    /// The earliest library that can read the format of the superblock
+    pub fn superblock_library(&self) -> &'static str {
+        match self.superblock_version {
+            0 | 1 => "HDF5 1.0",
+            2 => "HDF5 1.8",
+            _ => "HDF5 1.10",
+        }
+    }
+
+    /// Whether a writer has the file open, or did not close it, as recorded by the superblock
+    pub fn status(&self) -> &'static str {
+        match self.status_flags {
+            None if self.superblock_version < 2 => "not recorded by this superblock version",
+            None => "unknown",
+            Some(flags) if flags & SUPER_SWMR_WRITE_ACCESS != 0 => {
+                "open for SWMR writing, or its SWMR writer did not close it"
+            }
+            Some(flags) if flags & SUPER_WRITE_ACCESS != 0 => {
+                "open for writing, or its writer did not close it"
+            }
+            Some(_) => "closed cleanly",
+        }
+    }
+}
+
+/// Read the file consistency flags of a version 2 or 3 superblock, which follows the userblock
+fn read_status_flags(filename: &str, userblock_size: u64) -> std::io::Result<u8> {
+    let mut file = fs::File::open(filename)?;
+    file.seek(SeekFrom::Start(userblock_size + STATUS_FLAGS_OFFSET))?;
+    let mut flags = [0u8];
+    file.read_exact(&mut flags)?;
+    Ok(flags[0])
+}
+
+fn read_userblock(create_plist: hid_t) -> anyhow::Result<u64> {
+    let mut size: hsize_t = 0;
+    h5call!(H5Pget_userblock(create_plist, &mut size))?;
+    Ok(size)
+}
+
+fn read_libver_bounds(access_plist: hid_t) -> anyhow::Result<(String, String)> {
+    let mut low = H5F_libver_t::H5F_LIBVER_EARLIEST;
+    let mut high = H5F_libver_t::H5F_LIBVER_EARLIEST;
+    h5call!(H5Pget_libver_bounds(access_plist, &mut low, &mut high))?;
+    // The versions depend on the library, e.g. `H5F_LIBVER_V110`
+    let name = |version: H5F_libver_t| {
+        let name = format!("{version:?}");
+        name.trim_start_matches("H5F_LIBVER_").to_lowercase()
+    };
+    Ok((name(low), name(high)))
+}
+
+/// Open the file read-only as a SWMR reader
+///
+/// Files that a writer has open for SWMR writing, or that a SWMR writer did not close, fail to
+/// open otherwise, as their superblock records that they are open for writing.
+pub fn open_swmr_reader(path: &Path) -> anyhow::Result<hdf5::File> {
+    let filename = CString::new(path.to_str().context("The path is not valid UTF-8")?)?;
+    let fapl = SwmrReadFapl::new()?;
+    let id = h5call!(H5Fopen(
+        filename.as_ptr(),
+        H5F_ACC_RDONLY | H5F_ACC_SWMR_READ,
+        fapl.id()
+    ))?;
+    Ok(hdf5::from_id(id)?)
+}
@@ -1,11 +1,18 @@
-use std::fmt;
+use std::{cmp::Reverse, collections::HashMap, fmt, io::Write};
 
-use hdf5::{Dataset, H5Type};
+use hdf5::{Dataset, H5Type, Hyperslab, LocationType, Selection, SliceOrIndex};
 use ndarray::{ArrayD, Axis, NdProducer};
-use termcolor::{Color, StandardStream};
+use serde::Serialize;
+use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
 
-use crate::my_hdf5::util::NativePrimitiveType;
-use crate::util::{print_color, print_colored_quoted};
+use crate::my_hdf5::{
+    attribute::read_value,
+    file::FileMetadata,
+    link::Link,
+    storage::StorageInfo,
+    util::{type_name, with_native_type, NativePrimitiveType},
+};
+use crate::util::{format_bytes, glob_match, print_color, print_colored_quoted, print_table};
 
 pub fn print_dataset_info(
     dataset: &hdf5::Dataset,
@@ -18,18 +25,18 @@ pub fn print_dataset_info(
         "  - Dataset: ",
         format!("{}", dataset.name()),
     )?;
-    print_colored_quoted(
-        out,
-        Color::Yellow,
-        "     - Layout: ",
-        format!("{:?}", dataset.layout()),
-    )?;
-    print_colored_quoted(
-        out,
-        Color::Yellow,
-        "     - Attribute names: ",
-        format!("{:?}", dataset.attr_names().unwrap_or_default()),
-    )?;
+    match StorageInfo::read(dataset) {
+        Ok(storage) => print_storage(dataset, &storage, out)?,
+        Err(e) => log::warn!("Failed reading the storage of {}: {e}", dataset.name()),
+    }
+    match read_attributes(dataset) {
+        Ok(attributes) if attributes.is_empty() => (),
+        Ok(attributes) => {
+            print_color(out, Color::Yellow, "     - Attributes:")?;
+            print_attributes(&attributes, "       ", n_samples, out)?;
+        }
+        Err(e) => log::warn!("Failed reading the attributes of {}: {e}", dataset.name()),
+    }
     print_colored_quoted(
         out,
         Color::Yellow,
@@ -47,98 +54,980 @@ pub fn print_dataset_info(
     let dtype = dataset.dtype()?;
     let shape = dataset.shape();
 
+    print_colored_quoted(out, Color::Yellow, "     - Data type: ", type_name(&dtype))?;
+    print_colored_quoted(out, Color::Yellow, "     - Shape: ", format!("{shape:?}"))?;
+    log::trace!(" {dtype:?}, {}B", dtype.size());
+    if n_samples == 0 || shape.contains(&0) {
+        return Ok(());
+    }
+
+    // Only numeric datasets are previewed
+    let Some(native) = NativePrimitiveType::try_from_dtype(&dtype) else {
+        return Ok(());
+    };
+    with_native_type!(native, T, print_preview::<T>(dataset, n_samples, out)?);
+
+    Ok(())
+}
+
+/// Print the layout, chunks, filters, allocated size and fill value of the dataset
+fn print_storage(
+    dataset: &Dataset,
+    storage: &StorageInfo,
+    out: &mut StandardStream,
+) -> anyhow::Result<()> {
+    print_colored_quoted(out, Color::Yellow, "     - Layout: ", &storage.layout)?;
+    if let Some(chunks) = dataset.chunk() {
+        print_colored_quoted(out, Color::Yellow, "     - Chunks: ", format!("{chunks:?}"))?;
+    }
+    let filters = match storage.filters.is_empty() {
+        true => "none".to_owned(),
+        false => storage
+            .filters
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    print_colored_quoted(out, Color::Yellow, "     - Filters: ", filters)?;
+
+    let size = logical_size(dataset)?;
+    let ratio = match compression_ratio(size, storage.allocated) {
+        Some(ratio) => format!(", ratio {ratio:.2}"),
+        None => String::new(),
+    };
     print_colored_quoted(
         out,
         Color::Yellow,
-        "     - Data type: ",
-        format!("{}", NativePrimitiveType::from_dtype(&dtype)),
+        "     - Storage: ",
+        format!(
+            "{} allocated for {} of data{ratio}",
+            format_bytes(storage.allocated),
+            format_bytes(size)
+        ),
     )?;
-    print_colored_quoted(out, Color::Yellow, "     - Shape: ", format!("{shape:?}"))?;
-    log::trace!(" {dtype:?}, {}B", dtype.size());
 
-    match NativePrimitiveType::from_dtype(&dtype) {
-        NativePrimitiveType::Integer32b => {
-            let data = dataset.read_dyn()?;
-            print_preview_n_samples::<i32>(&data, n_samples, out)?;
+    let fill_value = match storage.fill_value {
+        Some(value) => format!("{value} ({})", storage.fill_value_source),
+        None => storage.fill_value_source.clone(),
+    };
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "     - Fill value: ",
+        format!(
+            "{fill_value}, allocation {}, written {}",
+            storage.alloc_time, storage.fill_time
+        ),
+    )?;
+    Ok(())
+}
+
+/// Size of the data in bytes, uncompressed
+fn logical_size(dataset: &Dataset) -> anyhow::Result<u64> {
+    Ok((dataset.size() * dataset.dtype()?.size()) as u64)
+}
+
+/// Logical size over allocated size, `None` if nothing is allocated yet
+fn compression_ratio(size: u64, allocated: u64) -> Option<f64> {
+    (allocated > 0).then(|| size as f64 / allocated as f64)
+}
+
+/// Print the size, format versions, free space and driver of the file
+pub fn print_file_metadata(
+    metadata: &FileMetadata,
+    out: &mut StandardStream,
+) -> anyhow::Result<()> {
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Size: ",
+        format!(
+            "{} ({} free)",
+            format_bytes(metadata.size),
+            format_bytes(metadata.free_space)
+        ),
+    )?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Superblock: ",
+        format!(
+            "version {}, readable by {} or later",
+            metadata.superblock_version,
+            metadata.superblock_library()
+        ),
+    )?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Library version bounds: ",
+        format!("{} to {}", metadata.libver_low, metadata.libver_high),
+    )?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Userblock: ",
+        format_bytes(metadata.userblock_size),
+    )?;
+    print_colored_quoted(out, Color::Yellow, "  - Driver: ", &metadata.driver)?;
+    let status = match metadata.swmr_reader {
+        true => format!("{}, opened as a SWMR reader", metadata.status()),
+        false => metadata.status().to_owned(),
+    };
+    print_colored_quoted(out, Color::Yellow, "  - Status: ", status)?;
+    Ok(())
+}
+
+/// How links are handled when walking the hierarchy
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LinkOptions {
+    /// Open the files of external links and list the objects they point to
+    pub follow_external: bool,
+    /// List the links whose targets do not exist at the end
+    pub report_dangling: bool,
+}
+
+/// Identity of an object, which several hard or soft links may point to: the file number and
+/// the token, formatted as the token is neither `Hash` nor `Ord`
+type ObjectKey = (u64, String);
+
+fn object_key(location: &hdf5::Location) -> hdf5::Result<ObjectKey> {
+    let info = location.loc_info()?;
+    Ok((info.fileno, format!("{:?}", info.token)))
+}
+
+/// Objects already listed, with the path they were first listed at
+#[derive(Debug, Default)]
+struct Visited(HashMap<ObjectKey, String>);
+
+impl Visited {
+    /// Record the object at `path`, or return the path it was already listed at
+    fn visit(&mut self, location: &hdf5::Location, path: &str) -> hdf5::Result<Option<String>> {
+        let key = object_key(location)?;
+        if let Some(first) = self.0.get(&key) {
+            return Ok(Some(first.clone()));
+        }
+        self.0.insert(key, path.to_owned());
+        Ok(None)
+    }
+
+    /// The path the object was first listed at
+    fn get(&self, location: &hdf5::Location) -> hdf5::Result<Option<&str>> {
+        Ok(self.0.get(&object_key(location)?).map(String::as_str))
+    }
+}
+
+/// Path of the member `name` of the group at `path`
+pub fn member_path(path: &str, name: &str) -> String {
+    format!("{}/{name}", path.trim_end_matches('/'))
+}
+
+/// Which objects below the starting group are listed when walking the hierarchy
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    /// Glob patterns of the paths to list, everything is listed if empty
+    pub include: Vec<String>,
+    /// Glob patterns of the paths to leave out, together with everything below them
+    pub exclude: Vec<String>,
+    /// Depth below the starting group down to which objects are listed
+    pub max_depth: Option<usize>,
+}
+
+impl PathFilter {
+    fn includes(&self, path: &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|p| glob_match(p, path))
+    }
+
+    fn excludes(&self, path: &str) -> bool {
+        self.exclude.iter().any(|p| glob_match(p, path))
+    }
+}
+
+/// Print the group, its attributes and everything below it, with the info and a preview of
+/// each dataset
+///
+/// Soft links are followed, external links only if requested. Objects reachable through
+/// several links are listed once, which also ends cycles of links, at their first hard link if
+/// they have one in the walked hierarchy. Groups that do not match the filter are still walked,
+/// as objects below them may match it.
+pub fn print_group_info(
+    group: &hdf5::Group,
+    n_samples: usize,
+    links: LinkOptions,
+    filter: &PathFilter,
+    out: &mut StandardStream,
+) -> anyhow::Result<()> {
+    let path = group.name();
+    let mut hard_links = Visited::default();
+    hard_links.visit(group, &path)?;
+    visit_hard_links(group, &path, 0, filter, &mut hard_links);
+    let mut walk = GroupWalk {
+        n_samples,
+        links,
+        filter,
+        hard_links,
+        visited: Visited::default(),
+        dangling: vec![],
+        out,
+    };
+    walk.visited.visit(group, &path)?;
+    walk.group(group, &path, 0)?;
+
+    if links.report_dangling {
+        let out = walk.out;
+        writeln!(out)?;
+        match walk.dangling.is_empty() {
+            true => print_color(out, Color::Green, "No dangling links")?,
+            false => {
+                print_color(
+                    out,
+                    Color::Red,
+                    format!("{} dangling links:", walk.dangling.len()),
+                )?;
+                for (path, target) in &walk.dangling {
+                    writeln!(out, "  {path} -> {target}")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Record the first hard link path of each object below the group that the walk reaches, at
+/// `depth` below the starting group, so that soft links to them are not listed in their place
+///
+/// Members that fail to be read are skipped, the walk reports them.
+fn visit_hard_links(
+    group: &hdf5::Group,
+    path: &str,
+    depth: usize,
+    filter: &PathFilter,
+    visited: &mut Visited,
+) {
+    if filter.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+        return;
+    }
+    let Ok(members) = group.member_names() else {
+        return;
+    };
+    for name in members {
+        let member = member_path(path, &name);
+        if filter.excludes(&member) || !matches!(Link::read(group, &name), Ok(Link::Hard)) {
+            continue;
+        }
+        match group.loc_type_by_name(&name) {
+            Ok(LocationType::Group) => {
+                if let Ok(subgroup) = group.group(&name) {
+                    if let Ok(None) = visited.visit(&subgroup, &member) {
+                        visit_hard_links(&subgroup, &member, depth + 1, filter, visited);
+                    }
+                }
+            }
+            Ok(LocationType::Dataset) => {
+                if let Ok(dataset) = group.dataset(&name) {
+                    let _ = visited.visit(&dataset, &member);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+struct GroupWalk<'a> {
+    n_samples: usize,
+    links: LinkOptions,
+    filter: &'a PathFilter,
+    /// Paths of the objects reachable through hard links, read before the walk
+    hard_links: Visited,
+    visited: Visited,
+    /// Paths and targets of the links whose targets do not exist
+    dangling: Vec<(String, String)>,
+    out: &'a mut StandardStream,
+}
+
+impl GroupWalk<'_> {
+    /// Print the group at `depth` below the starting group and walk its members
+    fn group(&mut self, group: &hdf5::Group, path: &str, depth: usize) -> anyhow::Result<()> {
+        let listed = depth == 0 || self.filter.includes(path);
+        if listed {
+            print_colored_quoted(self.out, Color::Cyan, "Group:", path)?;
+            match read_attributes(group) {
+                Ok(attributes) if attributes.is_empty() => (),
+                Ok(attributes) => {
+                    print_color(self.out, Color::Blue, "Attributes:")?;
+                    print_attributes(&attributes, "  ", self.n_samples, self.out)?;
+                }
+                Err(e) => log::warn!("Failed reading the attributes of {path}: {e}"),
+            }
+        }
+
+        let members = group.member_names()?;
+        if self
+            .filter
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            if listed && !members.is_empty() {
+                print_color(
+                    self.out,
+                    Color::Blue,
+                    format!("  - {} members below the maximum depth", members.len()),
+                )?;
+            }
+            return Ok(());
+        }
+        for name in members {
+            let member = member_path(path, &name);
+            if self.filter.excludes(&member) {
+                continue;
+            }
+            if let Err(e) = self.member(group, &name, &member, depth + 1) {
+                log::error!("Failed inspecting '{name}' in '{path}': {e}");
+            }
         }
-        NativePrimitiveType::Integer64b => {
-            let data = dataset.read_dyn()?;
-            print_preview_n_samples::<i64>(&data, n_samples, out)?;
+        Ok(())
+    }
+
+    fn member(
+        &mut self,
+        group: &hdf5::Group,
+        name: &str,
+        path: &str,
+        depth: usize,
+    ) -> anyhow::Result<()> {
+        let listed = self.filter.includes(path);
+        let link = Link::read(group, name)?;
+        let target = match &link {
+            Link::Hard => None,
+            Link::Soft { target } => Some(target.clone()),
+            Link::External { file, target } => Some(format!("{file}:{target}")),
+        };
+        if let (Some(target), true) = (&target, listed) {
+            let kind = match link {
+                Link::External { .. } => "External link:",
+                _ => "Soft link:",
+            };
+            print_colored_quoted(self.out, Color::Blue, kind, format!("{path} -> {target}"))?;
         }
-        NativePrimitiveType::UnsignedInteger32b => {
-            let data = dataset.read_dyn()?;
-            print_preview_n_samples::<u32>(&data, n_samples, out)?;
+        if matches!(link, Link::External { .. }) && !self.links.follow_external {
+            return Ok(());
         }
-        NativePrimitiveType::UnsignedInteger64b => {
-            let data = dataset.read_dyn()?;
-            print_preview_n_samples::<u64>(&data, n_samples, out)?;
+
+        let soft = target.is_some();
+        // Resolving the link fails if its target does not exist
+        let loc_type = match (group.loc_type_by_name(name), target) {
+            (Ok(loc_type), _) => loc_type,
+            (Err(_), Some(_)) if !listed => return Ok(()),
+            (Err(_), Some(target)) => {
+                print_color(
+                    self.out,
+                    Color::Red,
+                    format!("  - Dangling: {target} does not exist"),
+                )?;
+                self.dangling.push((path.to_owned(), target));
+                return Ok(());
+            }
+            (Err(e), None) => return Err(e.into()),
+        };
+        match loc_type {
+            LocationType::Group => {
+                let subgroup = group.group(name)?;
+                match self.listed_at(&subgroup, path, soft)? {
+                    Some(first) if listed => self.already_listed(path, &first)?,
+                    Some(_) => (),
+                    None => self.group(&subgroup, path, depth)?,
+                }
+            }
+            LocationType::Dataset if !listed => (),
+            LocationType::Dataset => {
+                let dataset = group.dataset(name)?;
+                match self.listed_at(&dataset, path, soft)? {
+                    Some(first) => self.already_listed(path, &first)?,
+                    None => {
+                        print_colored_quoted(self.out, Color::Magenta, "Dataset:", name)?;
+                        print_dataset_info(&dataset, self.n_samples, self.out)?;
+                    }
+                }
+            }
+            LocationType::NamedDatatype if !listed => (),
+            LocationType::NamedDatatype => {
+                print_colored_quoted(self.out, Color::Magenta, "Named datatype:", name)?
+            }
         }
-        NativePrimitiveType::Float32b => {
-            let data = dataset.read_dyn()?;
-            print_preview_n_samples::<f32>(&data, n_samples, out)?;
+        Ok(())
+    }
+
+    /// The path the object is listed at instead of `path`, if any: its hard link for objects
+    /// reached through a `soft` or external link, otherwise where it was first visited
+    fn listed_at(
+        &mut self,
+        location: &hdf5::Location,
+        path: &str,
+        soft: bool,
+    ) -> anyhow::Result<Option<String>> {
+        if soft {
+            if let Some(hard) = self.hard_links.get(location)? {
+                return Ok(Some(hard.to_owned()));
+            }
         }
-        NativePrimitiveType::Float64b => {
-            let data = dataset.read_dyn()?;
-            print_preview_n_samples::<f64>(&data, n_samples, out)?;
+        Ok(self.visited.visit(location, path)?)
+    }
+
+    fn already_listed(&mut self, path: &str, first: &str) -> anyhow::Result<()> {
+        print_colored_quoted(
+            self.out,
+            Color::Blue,
+            format!("  - {path} is listed as "),
+            first,
+        )
+    }
+}
+
+/// Structure of a file, as reported by `inspect --format json`
+#[derive(Debug, Clone, Serialize)]
+pub struct FileInfo {
+    pub filename: String,
+    pub metadata: FileMetadata,
+    /// The group inspected, the root group unless a path is given
+    pub root: GroupInfo,
+}
+
+impl FileInfo {
+    /// Read the structure of the file below `root` selected by the filter, without reading any
+    /// data
+    pub fn read(
+        file: &hdf5::File,
+        root: &hdf5::Group,
+        filter: &PathFilter,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            filename: file.filename(),
+            metadata: FileMetadata::read(file)?,
+            root: GroupInfo::read_filtered(root, filter)?,
+        })
+    }
+}
+
+/// A group, its attributes and everything below it
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupInfo {
+    pub path: String,
+    pub attributes: Vec<AttributeInfo>,
+    pub members: Vec<MemberInfo>,
+    /// Number of members left out at the maximum depth
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub below_max_depth: Option<usize>,
+}
+
+/// A link in a group and the object it points to
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberInfo {
+    pub name: String,
+    pub link: Link,
+    /// The linked object, `None` for soft and external links, which are not followed, for
+    /// objects already listed and for objects that failed to be read
+    pub object: Option<ObjectInfo>,
+    /// Path the object was already listed at, if several hard links point to it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub same_as: Option<String>,
+    /// Why the object could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ObjectInfo {
+    Group(GroupInfo),
+    Dataset(Box<DatasetInfo>),
+    NamedDatatype,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetInfo {
+    pub path: String,
+    pub dtype: String,
+    pub shape: Vec<usize>,
+    /// Maximum shape, `null` for unlimited dimensions
+    pub maxshape: Vec<Option<usize>>,
+    pub chunks: Option<Vec<usize>>,
+    /// Size of the data in bytes, uncompressed
+    pub size: u64,
+    /// Size over the allocated size, `null` if no space is allocated yet
+    pub compression_ratio: Option<f64>,
+    pub storage: StorageInfo,
+    pub attributes: Vec<AttributeInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub dtype: String,
+    pub shape: Vec<usize>,
+    /// The value of a scalar attribute or the list of values, `null` for unsupported types
+    pub value: serde_json::Value,
+    /// Why the attribute could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl GroupInfo {
+    /// Read the structure below the group, without reading any data
+    pub fn read(group: &hdf5::Group) -> anyhow::Result<Self> {
+        Self::read_filtered(group, &PathFilter::default())
+    }
+
+    /// Read the structure below the group selected by the filter, without reading any data
+    ///
+    /// Groups that do not match the filter are kept if objects below them do.
+    pub fn read_filtered(group: &hdf5::Group, filter: &PathFilter) -> anyhow::Result<Self> {
+        let mut visited = Visited::default();
+        let path = group.name();
+        visited.visit(group, &path)?;
+        Self::read_visited(group, path, 0, filter, &mut visited)
+    }
+
+    fn read_visited(
+        group: &hdf5::Group,
+        path: String,
+        depth: usize,
+        filter: &PathFilter,
+        visited: &mut Visited,
+    ) -> anyhow::Result<Self> {
+        let names = group.member_names()?;
+        if filter.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            return Ok(Self {
+                path,
+                attributes: read_attributes(group)?,
+                members: vec![],
+                below_max_depth: Some(names.len()),
+            });
         }
-        NativePrimitiveType::Pointer(_) => {
-            let data = dataset.read_dyn()?;
-            print_preview_n_samples::<usize>(&data, n_samples, out)?;
+        let mut members = vec![];
+        // Objects that fail to be read are recorded with the error, the others are still read
+        for name in names {
+            let member_path = member_path(&path, &name);
+            if filter.excludes(&member_path) {
+                continue;
+            }
+            let listed = filter.includes(&member_path);
+            let link = match Link::read(group, &name) {
+                Ok(link) => link,
+                Err(e) => {
+                    log::warn!("Skipping {member_path}, failed reading the link: {e}");
+                    continue;
+                }
+            };
+            let (object, same_as, error) = match link {
+                Link::Hard => {
+                    match ObjectInfo::read(group, &name, &member_path, depth, filter, visited) {
+                        Ok((object, same_as)) => (object, same_as, None),
+                        Err(e) => {
+                            log::warn!("Failed reading {member_path}: {e}");
+                            (None, None, Some(e.to_string()))
+                        }
+                    }
+                }
+                Link::Soft { .. } | Link::External { .. } => (None, None, None),
+            };
+            let keep = match &object {
+                Some(ObjectInfo::Group(subgroup)) => listed || !subgroup.members.is_empty(),
+                _ => listed,
+            };
+            if !keep {
+                continue;
+            }
+            members.push(MemberInfo {
+                name,
+                link,
+                object,
+                same_as,
+                error,
+            });
         }
+        Ok(Self {
+            path,
+            attributes: read_attributes(group)?,
+            members,
+            below_max_depth: None,
+        })
+    }
+}
+
+impl ObjectInfo {
+    /// Read the object linked as `name` in the group at `depth`, or the path it was already read
+    /// at. Only groups are read if the path does not match the filter.
+    fn read(
+        group: &hdf5::Group,
+        name: &str,
+        path: &str,
+        depth: usize,
+        filter: &PathFilter,
+        visited: &mut Visited,
+    ) -> anyhow::Result<(Option<Self>, Option<String>)> {
+        let object = match group.loc_type_by_name(name)? {
+            LocationType::Group => {
+                let subgroup = group.group(name)?;
+                if let Some(first) = visited.visit(&subgroup, path)? {
+                    return Ok((None, Some(first)));
+                }
+                Self::Group(GroupInfo::read_visited(
+                    &subgroup,
+                    path.to_owned(),
+                    depth + 1,
+                    filter,
+                    visited,
+                )?)
+            }
+            _ if !filter.includes(path) => return Ok((None, None)),
+            LocationType::Dataset => {
+                let dataset = group.dataset(name)?;
+                if let Some(first) = visited.visit(&dataset, path)? {
+                    return Ok((None, Some(first)));
+                }
+                Self::Dataset(Box::new(DatasetInfo::read(&dataset)?))
+            }
+            LocationType::NamedDatatype => Self::NamedDatatype,
+        };
+        Ok((Some(object), None))
+    }
+}
+
+impl DatasetInfo {
+    pub fn read(dataset: &Dataset) -> anyhow::Result<Self> {
+        let size = logical_size(dataset)?;
+        let storage = StorageInfo::read(dataset)?;
+        Ok(Self {
+            path: dataset.name(),
+            dtype: type_name(&dataset.dtype()?),
+            shape: dataset.shape(),
+            maxshape: dataset.space()?.extents()?.maxdims(),
+            chunks: dataset.chunk(),
+            size,
+            compression_ratio: compression_ratio(size, storage.allocated),
+            storage,
+            attributes: read_attributes(dataset)?,
+        })
     }
+}
 
+/// Read the attributes of a group or dataset with their values
+///
+/// Attributes that fail to be read are recorded with the error, the others are still read.
+fn read_attributes(location: &hdf5::Location) -> anyhow::Result<Vec<AttributeInfo>> {
+    let mut attributes = vec![];
+    for name in location.attr_names()? {
+        let info = match read_attribute(location, &name) {
+            Ok(info) => info,
+            Err(e) => {
+                log::warn!(
+                    "Failed reading attribute {name} of {}: {e}",
+                    location.name()
+                );
+                AttributeInfo {
+                    name,
+                    dtype: "unknown".to_owned(),
+                    shape: vec![],
+                    value: serde_json::Value::Null,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        attributes.push(info);
+    }
+    Ok(attributes)
+}
+
+fn read_attribute(location: &hdf5::Location, name: &str) -> anyhow::Result<AttributeInfo> {
+    let attr = location.attr(name)?;
+    let dtype = type_name(&attr.dtype()?);
+    let (value, error) = match read_value(&attr) {
+        Ok(value) => (value, None),
+        Err(e) => {
+            log::warn!(
+                "Failed reading attribute {name} of {}: {e}",
+                location.name()
+            );
+            (serde_json::Value::Null, Some(e.to_string()))
+        }
+    };
+    Ok(AttributeInfo {
+        name: name.to_owned(),
+        dtype,
+        shape: attr.shape(),
+        value,
+        error,
+    })
+}
+
+/// Print the attributes with their values, one per line, lists longer than `max_items` are
+/// truncated
+pub fn print_attributes(
+    attributes: &[AttributeInfo],
+    indent: &str,
+    max_items: usize,
+    out: &mut StandardStream,
+) -> anyhow::Result<()> {
+    for attr in attributes {
+        let value = match (&attr.value, &attr.error) {
+            (_, Some(error)) => format!("(unreadable: {error})"),
+            (serde_json::Value::Null, None) => format!("({} is not supported)", attr.dtype),
+            (serde_json::Value::String(s), None) => s.clone(),
+            (value, None) => format_value(value, max_items),
+        };
+        let shape = match attr.shape.is_empty() {
+            true => String::new(),
+            false => format!(" {:?}", attr.shape),
+        };
+        print_colored_quoted(
+            out,
+            Color::Blue,
+            format!("{indent}- {} ({}{shape}): ", attr.name, attr.dtype),
+            value,
+        )?;
+    }
     Ok(())
 }
 
-pub fn print_group_info(
-    group: &hdf5::Group,
-    n_samples: usize,
+/// Short text of a value, lists longer than `max_items` are truncated
+fn format_value(value: &serde_json::Value, max_items: usize) -> String {
+    use serde_json::Value;
+
+    match value {
+        Value::Array(items) => {
+            let mut texts: Vec<String> = items
+                .iter()
+                .take(max_items)
+                .map(|item| format_value(item, max_items))
+                .collect();
+            if items.len() > max_items {
+                texts.push(format!("… {} more", items.len() - max_items));
+            }
+            format!("[{}]", texts.join(", "))
+        }
+        Value::Object(fields) => {
+            let texts: Vec<String> = fields
+                .iter()
+                .map(|(name, value)| format!("{name}: {}", format_value(value, max_items)))
+                .collect();
+            format!("{{{}}}", texts.join(", "))
+        }
+        Value::String(s) => format!("{s:?}"),
+        value => value.to_string(),
+    }
+}
+
+/// Print the attributes of the group and of everything below it, without the data
+pub fn print_attributes_only(
+    group: &GroupInfo,
+    max_items: usize,
     out: &mut StandardStream,
 ) -> anyhow::Result<()> {
-    let gname = group.name();
-    print_colored_quoted(out, Color::Cyan, "Group:", format!("{gname}"))?;
+    print_colored_quoted(out, Color::Cyan, "Group:", &group.path)?;
+    print_attributes(&group.attributes, "  ", max_items, out)?;
+    for member in &group.members {
+        match &member.object {
+            Some(ObjectInfo::Group(subgroup)) => print_attributes_only(subgroup, max_items, out)?,
+            Some(ObjectInfo::Dataset(dataset)) => {
+                print_colored_quoted(out, Color::Magenta, "Dataset:", &dataset.path)?;
+                print_attributes(&dataset.attributes, "  ", max_items, out)?;
+            }
+            Some(ObjectInfo::NamedDatatype) | None => (),
+        }
+    }
+    Ok(())
+}
+
+/// Number of objects listed by [`print_tree`]
+#[derive(Debug, Default)]
+struct TreeCounts {
+    groups: usize,
+    datasets: usize,
+}
+
+/// Print the hierarchy below the group like `tree`, with the type, shape and size of each
+/// dataset and the targets of soft and external links
+pub fn print_tree(group: &GroupInfo, out: &mut StandardStream) -> anyhow::Result<()> {
+    print_color(out, Color::Cyan, &group.path)?;
+    let mut counts = TreeCounts::default();
+    print_tree_members(group, "", &mut counts, out)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{} groups, {} datasets",
+        counts.groups, counts.datasets
+    )?;
+    Ok(())
+}
 
-    if let Ok(attr) = group.attr_names() {
-        print_colored_quoted(out, Color::Blue, "Attributes:", format!("{attr:?}"))?;
-    } else {
-        log::warn!("No attributes found");
+fn print_tree_members(
+    group: &GroupInfo,
+    prefix: &str,
+    counts: &mut TreeCounts,
+    out: &mut StandardStream,
+) -> anyhow::Result<()> {
+    if let Some(n) = group.below_max_depth.filter(|&n| n > 0) {
+        writeln!(out, "{prefix}└── {n} members below the maximum depth")?;
+    }
+    for (i, member) in group.members.iter().enumerate() {
+        let (connector, indent) = match i + 1 == group.members.len() {
+            true => ("└── ", "    "),
+            false => ("├── ", "│   "),
+        };
+        write!(out, "{prefix}{connector}")?;
+        let name = &member.name;
+        match (&member.object, &member.link) {
+            (Some(ObjectInfo::Group(subgroup)), _) => {
+                counts.groups += 1;
+                print_color(out, Color::Cyan, format!("{name}/"))?;
+                print_tree_members(subgroup, &format!("{prefix}{indent}"), counts, out)?;
+            }
+            (Some(ObjectInfo::Dataset(dataset)), _) => {
+                counts.datasets += 1;
+                out.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+                write!(out, "{name}")?;
+                out.reset()?;
+                let on_disk = match dataset.storage.allocated == dataset.size {
+                    true => String::new(),
+                    false => format!(" ({} on disk)", format_bytes(dataset.storage.allocated)),
+                };
+                writeln!(
+                    out,
+                    "  {}  {:?}  {}{on_disk}",
+                    dataset.dtype,
+                    dataset.shape,
+                    format_bytes(dataset.size)
+                )?;
+            }
+            (Some(ObjectInfo::NamedDatatype), _) => writeln!(out, "{name} (named datatype)")?,
+            (None, Link::Soft { target }) => {
+                print_color(out, Color::Blue, format!("{name} -> {target}"))?
+            }
+            (None, Link::External { file, target }) => {
+                print_color(out, Color::Blue, format!("{name} -> {file}:{target}"))?
+            }
+            (None, Link::Hard) => match (&member.same_as, &member.error) {
+                (Some(first), _) => print_color(out, Color::Blue, format!("{name} = {first}"))?,
+                (None, Some(error)) => {
+                    print_color(out, Color::Red, format!("{name} (unreadable: {error})"))?
+                }
+                (None, None) => print_color(out, Color::Red, format!("{name} (unreadable)"))?,
+            },
+        }
     }
+    Ok(())
+}
 
-    for member in group.member_names()? {
-        print_colored_quoted(out, Color::Magenta, "Dataset:", format!("{member}"))?;
-        if let Ok(dataset) = group.dataset(&member) {
-            if let Ok(dataset) = dataset.as_dataset() {
-                print_dataset_info(&dataset, n_samples, out)?;
-            } else if let Ok(subgroup) = dataset.as_group() {
-                print_group_info(&subgroup, n_samples, out)?;
-            } else {
-                log::error!("Unhandled object type for: {member}");
+impl GroupInfo {
+    /// The datasets of the group and of everything below it
+    pub fn datasets(&self) -> Vec<&DatasetInfo> {
+        let mut datasets = vec![];
+        for member in &self.members {
+            match &member.object {
+                Some(ObjectInfo::Group(subgroup)) => datasets.extend(subgroup.datasets()),
+                Some(ObjectInfo::Dataset(dataset)) => datasets.push(dataset),
+                Some(ObjectInfo::NamedDatatype) | None => (),
             }
-        } else {
-            log::error!("Failed opening dataset: '{member}' in '{gname}'");
         }
+        datasets
     }
+}
+
+/// Print a table of the datasets below the group, the largest on disk first
+pub fn print_datasets_by_size(group: &GroupInfo, out: &mut StandardStream) -> anyhow::Result<()> {
+    let mut datasets = group.datasets();
+    datasets.sort_by_key(|dataset| Reverse(dataset.storage.allocated));
 
+    let header = [
+        "Dataset", "Type", "Shape", "Size", "On disk", "Ratio", "Filters",
+    ];
+    let rows: Vec<Vec<String>> = datasets
+        .iter()
+        .map(|dataset| {
+            let filters: Vec<String> = dataset
+                .storage
+                .filters
+                .iter()
+                .map(|f| f.name.clone())
+                .collect();
+            vec![
+                dataset.path.clone(),
+                dataset.dtype.clone(),
+                format!("{:?}", dataset.shape),
+                format_bytes(dataset.size),
+                format_bytes(dataset.storage.allocated),
+                dataset
+                    .compression_ratio
+                    .map_or("-".to_owned(), |ratio| format!("{ratio:.2}")),
+                match filters.is_empty() {
+                    true => "-".to_owned(),
+                    false => filters.join(","),
+                },
+            ]
+        })
+        .collect();
+    print_table(out, Color::Cyan, &header, &rows)?;
+
+    let allocated: u64 = datasets
+        .iter()
+        .map(|dataset| dataset.storage.allocated)
+        .sum();
+    let size: u64 = datasets.iter().map(|dataset| dataset.size).sum();
+    writeln!(
+        out,
+        "\n{} datasets, {} allocated for {} of data",
+        datasets.len(),
+        format_bytes(allocated),
+        format_bytes(size)
+    )?;
     Ok(())
 }
 
+/// Read the first `n` elements along each axis of the dataset and print the preview of them
+fn print_preview<T: H5Type + fmt::Display + std::fmt::Debug>(
+    dataset: &Dataset,
+    n: usize,
+    out: &mut StandardStream,
+) -> anyhow::Result<()> {
+    let shape = dataset.shape();
+    let data = match shape.is_empty() {
+        // A scalar has a single element
+        true => dataset.read_dyn::<T>()?,
+        false => {
+            let slices: Vec<SliceOrIndex> = shape
+                .iter()
+                .map(|&len| SliceOrIndex::from(0..len.min(n)))
+                .collect();
+            dataset.read_slice::<T, _, _>(Selection::from(Hyperslab::from(slices)))?
+        }
+    };
+    log::debug!(
+        "Read a preview of shape {:?} from {}",
+        data.shape(),
+        dataset.name()
+    );
+    print_preview_n_samples(&data, &shape, n, out)
+}
+
+/// Print the preview of `data`, the first elements of a dataset of the given `shape`
 pub fn print_preview_n_samples<T: H5Type + fmt::Display + std::fmt::Debug>(
     data: &ArrayD<T>,
+    shape: &[usize],
     n: usize,
     out: &mut StandardStream,
 ) -> anyhow::Result<()> {
     take_n_from_dims_print(data, n, out)?;
-    take_n_from_axes_print(data, n, out)?;
+    take_n_from_axes_print(data, shape, n, out)?;
     Ok(())
 }
 
 pub fn take_n_from_axes_print<T: H5Type + fmt::Display>(
     data: &ArrayD<T>,
+    shape: &[usize],
     n: usize,
     out: &mut StandardStream,
 ) -> anyhow::Result<()> {
-    // Get the shape of the dataset
-    let shape = data.shape();
     log::debug!("{shape:?}");
     let dims = data.ndim();
 
@@ -0,0 +1,83 @@
+use std::ffi::{c_char, c_uint, CStr, CString};
+
+use anyhow::bail;
+use hdf5::h5call;
+use hdf5_sys::h5l::{H5L_info1_t, H5L_type_t, H5Lget_info1, H5Lget_val, H5Lunpack_elink_val};
+use hdf5_sys::h5p::H5P_DEFAULT;
+use serde::Serialize;
+
+/// The kind of a link in a group, and the target of soft and external links
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Link {
+    Hard,
+    /// Link to a path in the same file
+    Soft {
+        target: String,
+    },
+    /// Link to a path in another file
+    External {
+        file: String,
+        target: String,
+    },
+}
+
+impl Link {
+    /// Read the link `name` of the group, without resolving it
+    #[allow(deprecated)]
+    pub fn read(group: &hdf5::Group, name: &str) -> anyhow::Result<Self> {
+        let c_name = CString::new(name)?;
+        let mut info = H5L_info1_t::default();
+        h5call!(H5Lget_info1(
+            group.id(),
+            c_name.as_ptr(),
+            &mut info,
+            H5P_DEFAULT
+        ))?;
+
+        let link_type = info.type_;
+        if link_type == H5L_type_t::H5L_TYPE_HARD {
+            return Ok(Self::Hard);
+        }
+        if !matches!(
+            link_type,
+            H5L_type_t::H5L_TYPE_SOFT | H5L_type_t::H5L_TYPE_EXTERNAL
+        ) {
+            bail!("Unsupported link type {link_type:?} of {name}");
+        }
+
+        // Soft links store the target path, external links the file name and the path
+        let size = unsafe { *info.u.val_size() };
+        let mut value = vec![0u8; size];
+        h5call!(H5Lget_val(
+            group.id(),
+            c_name.as_ptr(),
+            value.as_mut_ptr().cast(),
+            size,
+            H5P_DEFAULT
+        ))?;
+
+        if link_type == H5L_type_t::H5L_TYPE_SOFT {
+            let target = CStr::from_bytes_until_nul(&value)?;
+            return Ok(Self::Soft {
+                target: target.to_string_lossy().into_owned(),
+            });
+        }
+        let mut flags: c_uint = 0;
+        let mut file: *const c_char = std::ptr::null();
+        let mut target: *const c_char = std::ptr::null();
+        h5call!(H5Lunpack_elink_val(
+            value.as_ptr().cast(),
+            size,
+            &mut flags,
+            &mut file,
+            &mut target
+        ))?;
+        // Both point into `value`
+        let (file, target) = unsafe { (CStr::from_ptr(file), CStr::from_ptr(target)) };
+        Ok(Self::External {
+            file: file.to_string_lossy().into_owned(),
+            target: target.to_string_lossy().into_owned(),
+        })
+    }
+}
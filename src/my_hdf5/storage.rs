@@ -0,0 +1,203 @@
+//! How the data of a dataset is stored in the file: the filter pipeline, the allocated size, and
+//! the fill value and allocation time of the dataset creation properties
+
+use std::{
+    ffi::{c_char, c_uint, CStr},
+    fmt,
+};
+
+use hdf5::{h5call, Dataset};
+use hdf5_sys::{
+    h5d::{H5D_alloc_time_t, H5D_fill_time_t, H5D_fill_value_t, H5Dget_create_plist},
+    h5i::hid_t,
+    h5p::{
+        H5Pclose, H5Pfill_value_defined, H5Pget_alloc_time, H5Pget_fill_time, H5Pget_fill_value,
+        H5Pget_filter2, H5Pget_nfilters,
+    },
+    h5t::H5T_NATIVE_DOUBLE,
+    h5z::H5Z_FLAG_OPTIONAL,
+};
+use serde::Serialize;
+
+use crate::my_hdf5::util::NativePrimitiveType;
+
+/// Most parameters read for a filter, filters rarely have more than a handful
+const MAX_FILTER_PARAMS: usize = 16;
+
+/// Storage of a dataset, read from its creation properties
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageInfo {
+    pub layout: String,
+    /// Filters applied to each chunk, in the order they are applied when writing
+    pub filters: Vec<FilterInfo>,
+    /// Bytes allocated in the file for the data, compressed
+    pub allocated: u64,
+    /// Fill value of numeric datasets, `null` if it is undefined or the type is not numeric
+    pub fill_value: Option<f64>,
+    /// Whether the fill value is `undefined`, the library `default` or `user` defined
+    pub fill_value_source: String,
+    /// When space for the data is allocated: `early`, `late`, `incremental` or `default`
+    pub alloc_time: String,
+    /// When the fill value is written: `alloc`, `never` or `ifset`
+    pub fill_time: String,
+}
+
+/// A filter of the pipeline, the parameters are the client data values stored in the file
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterInfo {
+    pub id: i32,
+    pub name: String,
+    pub params: Vec<u32>,
+    /// Chunks are stored unfiltered when an optional filter fails
+    pub optional: bool,
+}
+
+impl StorageInfo {
+    pub fn read(dataset: &Dataset) -> anyhow::Result<Self> {
+        let plist = h5call!(H5Dget_create_plist(dataset.id()))?;
+        let storage = Self::read_plist(dataset, plist);
+        h5call!(H5Pclose(plist))?;
+        storage
+    }
+
+    fn read_plist(dataset: &Dataset, plist: hid_t) -> anyhow::Result<Self> {
+        let mut fill_value_status = H5D_fill_value_t::H5D_FILL_VALUE_UNDEFINED;
+        h5call!(H5Pfill_value_defined(plist, &mut fill_value_status))?;
+        let mut alloc_time = H5D_alloc_time_t::H5D_ALLOC_TIME_DEFAULT;
+        h5call!(H5Pget_alloc_time(plist, &mut alloc_time))?;
+        let mut fill_time = H5D_fill_time_t::H5D_FILL_TIME_ALLOC;
+        h5call!(H5Pget_fill_time(plist, &mut fill_time))?;
+
+        // The library converts the fill value to the memory type
+        let numeric = NativePrimitiveType::try_from_dtype(&dataset.dtype()?).is_some();
+        let fill_value = match fill_value_status {
+            H5D_fill_value_t::H5D_FILL_VALUE_DEFAULT
+            | H5D_fill_value_t::H5D_FILL_VALUE_USER_DEFINED
+                if numeric =>
+            {
+                let mut value = 0.0f64;
+                h5call!(H5Pget_fill_value(
+                    plist,
+                    *H5T_NATIVE_DOUBLE,
+                    (&mut value as *mut f64).cast()
+                ))?;
+                Some(value)
+            }
+            _ => None,
+        };
+
+        let fill_value_source = match fill_value_status {
+            H5D_fill_value_t::H5D_FILL_VALUE_UNDEFINED => "undefined",
+            H5D_fill_value_t::H5D_FILL_VALUE_DEFAULT => "default",
+            H5D_fill_value_t::H5D_FILL_VALUE_USER_DEFINED => "user",
+            H5D_fill_value_t::H5D_FILL_VALUE_ERROR => "unknown",
+        };
+        let alloc_time = match alloc_time {
+            H5D_alloc_time_t::H5D_ALLOC_TIME_DEFAULT => "default",
+            H5D_alloc_time_t::H5D_ALLOC_TIME_EARLY => "early",
+            H5D_alloc_time_t::H5D_ALLOC_TIME_LATE => "late",
+            H5D_alloc_time_t::H5D_ALLOC_TIME_INCR => "incremental",
+            H5D_alloc_time_t::H5D_ALLOC_TIME_ERROR => "unknown",
+        };
+        let fill_time = match fill_time {
+            H5D_fill_time_t::H5D_FILL_TIME_ALLOC => "alloc",
+            H5D_fill_time_t::H5D_FILL_TIME_NEVER => "never",
+            H5D_fill_time_t::H5D_FILL_TIME_IFSET => "ifset",
+            H5D_fill_time_t::H5D_FILL_TIME_ERROR => "unknown",
+        };
+
+        Ok(Self {
+            layout: format!("{:?}", dataset.layout()).to_lowercase(),
+            filters: read_filters(plist)?,
+            allocated: dataset.storage_size(),
+            fill_value,
+            fill_value_source: fill_value_source.to_owned(),
+            alloc_time: alloc_time.to_owned(),
+            fill_time: fill_time.to_owned(),
+        })
+    }
+}
+
+/// Read the filter pipeline of a dataset creation property list
+fn read_filters(plist: hid_t) -> anyhow::Result<Vec<FilterInfo>> {
+    let count = h5call!(H5Pget_nfilters(plist))?;
+    let mut filters = vec![];
+    for index in 0..count as c_uint {
+        let mut flags: c_uint = 0;
+        let mut n_params = MAX_FILTER_PARAMS;
+        let mut params: Vec<c_uint> = vec![0; MAX_FILTER_PARAMS];
+        let mut name = [0 as c_char; 256];
+        let mut config: c_uint = 0;
+        let id = h5call!(H5Pget_filter2(
+            plist,
+            index,
+            &mut flags,
+            &mut n_params,
+            params.as_mut_ptr(),
+            name.len(),
+            name.as_mut_ptr(),
+            &mut config
+        ))?;
+        params.truncate(n_params.min(MAX_FILTER_PARAMS));
+
+        // Third-party filters are named by their plugin, if it is loaded
+        let name = unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy();
+        let name = match (known_filter_name(id), name.is_empty()) {
+            (Some(known), _) => known.to_owned(),
+            (None, false) => name.into_owned(),
+            (None, true) => format!("filter {id}"),
+        };
+        filters.push(FilterInfo {
+            id,
+            name,
+            params,
+            optional: flags & H5Z_FLAG_OPTIONAL != 0,
+        });
+    }
+    Ok(filters)
+}
+
+/// Short names of the filters built into the library and of common registered filters
+fn known_filter_name(id: i32) -> Option<&'static str> {
+    let name = match id {
+        1 => "deflate",
+        2 => "shuffle",
+        3 => "fletcher32",
+        4 => "szip",
+        5 => "nbit",
+        6 => "scaleoffset",
+        307 => "bzip2",
+        32000 => "lzf",
+        32001 => "blosc",
+        32004 => "lz4",
+        32008 => "bitshuffle",
+        32015 => "zstd",
+        _ => return None,
+    };
+    Some(name)
+}
+
+impl fmt::Display for FilterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let param = |i: usize| self.params.get(i).copied();
+        match (self.id, param(0), param(1)) {
+            (1, Some(level), _) => write!(f, "deflate (level {level})")?,
+            (4, Some(mask), Some(pixels)) => {
+                // H5_SZIP_EC_OPTION_MASK and H5_SZIP_NN_OPTION_MASK
+                let coding = match (mask & 4 != 0, mask & 32 != 0) {
+                    (_, true) => "nn",
+                    (true, false) => "ec",
+                    (false, false) => "?",
+                };
+                write!(f, "szip ({coding}, {pixels} pixels per block)")?
+            }
+            (id, _, _) if id < 256 => write!(f, "{}", self.name)?,
+            // Registered third-party filter
+            (id, _, _) => write!(f, "{} (id {id}, params {:?})", self.name, self.params)?,
+        }
+        if self.optional {
+            write!(f, ", optional")?;
+        }
+        Ok(())
+    }
+}
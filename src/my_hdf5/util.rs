@@ -1,9 +1,15 @@
-use std::mem::size_of;
+use std::{mem::size_of, str::FromStr};
+
+use anyhow::bail;
 
 use hdf5::Datatype;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NativePrimitiveType {
+    Integer8b,
+    Integer16b,
+    UnsignedInteger8b,
+    UnsignedInteger16b,
     Integer32b,
     Integer64b,
     UnsignedInteger32b,
@@ -14,24 +20,136 @@ pub enum NativePrimitiveType {
 }
 
 impl NativePrimitiveType {
-    pub fn from_dtype(dtype: &Datatype) -> Self {
-        if dtype.is::<i32>() {
-            return Self::Integer32b;
+    /// The native type of `dtype`, `None` if it is not a primitive numeric type
+    pub fn try_from_dtype(dtype: &Datatype) -> Option<Self> {
+        let native = if dtype.is::<i8>() {
+            Self::Integer8b
+        } else if dtype.is::<i16>() {
+            Self::Integer16b
+        } else if dtype.is::<u8>() {
+            Self::UnsignedInteger8b
+        } else if dtype.is::<u16>() {
+            Self::UnsignedInteger16b
+        } else if dtype.is::<i32>() {
+            Self::Integer32b
         } else if dtype.is::<i64>() {
-            return Self::Integer64b;
-        } else if dtype.is::<usize>() {
-            return Self::Pointer(size_of::<usize>());
+            Self::Integer64b
         } else if dtype.is::<u32>() {
-            return Self::UnsignedInteger32b;
+            Self::UnsignedInteger32b
         } else if dtype.is::<u64>() {
-            return Self::UnsignedInteger64b;
+            Self::UnsignedInteger64b
+        } else if dtype.is::<usize>() {
+            // Only reached if `usize` is neither 32 nor 64 bits wide
+            Self::Pointer(size_of::<usize>())
         } else if dtype.is::<f32>() {
-            return Self::Float32b;
+            Self::Float32b
         } else if dtype.is::<f64>() {
-            return Self::Float64b;
+            Self::Float64b
         } else {
-            todo!("Unsupported datatype");
+            return None;
+        };
+        Some(native)
+    }
+
+    /// Short name of the type, e.g. `u32`
+    pub fn name(&self) -> &'static str {
+        match self {
+            NativePrimitiveType::Integer8b => "i8",
+            NativePrimitiveType::Integer16b => "i16",
+            NativePrimitiveType::UnsignedInteger8b => "u8",
+            NativePrimitiveType::UnsignedInteger16b => "u16",
+            NativePrimitiveType::Integer32b => "i32",
+            NativePrimitiveType::Integer64b => "i64",
+            NativePrimitiveType::UnsignedInteger32b => "u32",
+            NativePrimitiveType::UnsignedInteger64b => "u64",
+            NativePrimitiveType::Float32b => "f32",
+            NativePrimitiveType::Float64b => "f64",
+            // Named as the unsigned integer of the same size, as the file does not record it
+            NativePrimitiveType::Pointer(1) => "u8",
+            NativePrimitiveType::Pointer(2) => "u16",
+            NativePrimitiveType::Pointer(4) => "u32",
+            NativePrimitiveType::Pointer(_) => "u64",
+        }
+    }
+}
+
+/// Evaluate `$body` with `$t` an alias of the Rust type of a [`NativePrimitiveType`], e.g.
+/// `with_native_type!(native, T, read_samples_as::<T>(dataset))`
+///
+/// Pointer-sized integers are `usize`.
+macro_rules! with_native_type {
+    ($native:expr, $t:ident, $body:expr) => {
+        match $native {
+            $crate::my_hdf5::util::NativePrimitiveType::Integer8b => {
+                type $t = i8;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::Integer16b => {
+                type $t = i16;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::UnsignedInteger8b => {
+                type $t = u8;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::UnsignedInteger16b => {
+                type $t = u16;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::Integer32b => {
+                type $t = i32;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::Integer64b => {
+                type $t = i64;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::UnsignedInteger32b => {
+                type $t = u32;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::UnsignedInteger64b => {
+                type $t = u64;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::Pointer(_) => {
+                type $t = usize;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::Float32b => {
+                type $t = f32;
+                $body
+            }
+            $crate::my_hdf5::util::NativePrimitiveType::Float64b => {
+                type $t = f64;
+                $body
+            }
         }
+    };
+}
+pub(crate) use with_native_type;
+
+/// Parse the short name of a type, e.g. `u32`, as given by [`NativePrimitiveType::name`]
+impl FromStr for NativePrimitiveType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let native = match s.to_ascii_lowercase().as_str() {
+            "i8" => Self::Integer8b,
+            "i16" => Self::Integer16b,
+            "i32" => Self::Integer32b,
+            "i64" => Self::Integer64b,
+            "u8" => Self::UnsignedInteger8b,
+            "u16" => Self::UnsignedInteger16b,
+            "u32" => Self::UnsignedInteger32b,
+            "u64" => Self::UnsignedInteger64b,
+            "f32" => Self::Float32b,
+            "f64" => Self::Float64b,
+            _ => bail!(
+                "Unknown type '{s}', expected one of i8, i16, i32, i64, u8, u16, u32, u64, f32 or f64"
+            ),
+        };
+        Ok(native)
     }
 }
 
@@ -39,6 +157,10 @@ impl std::fmt::Display for NativePrimitiveType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             NativePrimitiveType::Pointer(size) => write!(f, "{}-bit pointer", size * 8),
+            NativePrimitiveType::Integer8b => write!(f, "8-bit integer"),
+            NativePrimitiveType::Integer16b => write!(f, "16-bit integer"),
+            NativePrimitiveType::UnsignedInteger8b => write!(f, "8-bit unsigned integer"),
+            NativePrimitiveType::UnsignedInteger16b => write!(f, "16-bit unsigned integer"),
             NativePrimitiveType::Integer32b => write!(f, "32-bit integer"),
             NativePrimitiveType::Integer64b => write!(f, "64-bit integer"),
             NativePrimitiveType::UnsignedInteger32b => write!(f, "32-bit unsigned integer"),
@@ -48,3 +170,15 @@ impl std::fmt::Display for NativePrimitiveType {
         }
     }
 }
+
+/// Short name of any type, e.g. `f32` for primitive types, and the name of the type descriptor
+/// for others, e.g. `string (var len)` or `compound (3 fields)`
+pub fn type_name(dtype: &Datatype) -> String {
+    match NativePrimitiveType::try_from_dtype(dtype) {
+        Some(native) => native.name().to_owned(),
+        None => match dtype.to_descriptor() {
+            Ok(descriptor) => descriptor.to_string(),
+            Err(_) => format!("unknown ({} bytes)", dtype.size()),
+        },
+    }
+}
@@ -3,262 +3,340 @@ pub mod rplotly;
 #[cfg(features = "rplotters")]
 pub mod rplotters;
 
-use std::{
-    fmt::{self, Display},
-    fs,
-    ops::{Add, Div, Mul, Sub},
-};
-
-use anyhow::bail;
-use hdf5::{Dataset, H5Type};
-use ndarray::{Array1, Array2, ArrayBase, Axis, Data, Ix1};
-use num_traits::{real::Real, Bounded, FromPrimitive, ToPrimitive, Zero};
-use serde::Serialize;
+use anyhow::{bail, Context};
+use hdf5::Dataset;
+use num_traits::ToPrimitive;
 
 use crate::{
-    config::{Config, PlotArgs},
-    my_hdf5::util::NativePrimitiveType,
+    config::{misc::Transform, Config, PlotArgs},
+    events::{Event, EventDetector},
+    expr::Expression,
+    my_hdf5::{
+        cf::Packing,
+        chunked::{for_each_mapped, for_each_sample, sample_count, Sample},
+        util::{type_name, with_native_type, NativePrimitiveType},
+    },
+    resample::Alignment,
+    stats::{
+        accumulator::{Accumulate, StatsAccumulator},
+        rolling::Rolling,
+        transform::{positioned, transformed, Transformed},
+        Statistics,
+    },
 };
 
 pub fn handle_plot_cmd(plot_args: &PlotArgs, cfg: &Config) -> anyhow::Result<()> {
+    if plot_args.rolling == Some(0) {
+        bail!("The rolling window must contain at least 1 sample");
+    }
+
     // Open the HDF5 file
     log::debug!("opening: {:?}", plot_args.src_hdf5.as_path());
     let file = hdf5::File::open(plot_args.src_hdf5.as_path())?;
 
-    let dataset = file.dataset(&plot_args.dataset_name)?;
-    let dtype = dataset.dtype()?;
+    let x_axis = match &plot_args.x_axis {
+        Some(name) => Some(file.dataset(name)?),
+        None => None,
+    };
+    let mut datasets: Vec<&str> = plot_args.dataset_name.iter().map(String::as_str).collect();
+    datasets.extend(
+        plot_args
+            .exprs
+            .iter()
+            .flat_map(|e| e.refs())
+            .map(|r| r.path.as_str()),
+    );
+    let alignment = Alignment::new(
+        &file,
+        &plot_args.resampling,
+        &datasets,
+        plot_args.x_axis.as_deref(),
+    )?;
 
-    match NativePrimitiveType::from_dtype(&dtype) {
-        NativePrimitiveType::Integer32b => {
-            read_and_process_dataset_nonfloats::<u32>(
-                &dataset,
-                plot_args.axis,
-                plot_args.subsample,
-            )?;
-        }
-        NativePrimitiveType::Integer64b => {
-            read_and_process_dataset_nonfloats::<u64>(
-                &dataset,
-                plot_args.axis,
-                plot_args.subsample,
-            )?;
-        }
-        NativePrimitiveType::UnsignedInteger32b => {
-            read_and_process_dataset_nonfloats::<i32>(
-                &dataset,
-                plot_args.axis,
-                plot_args.subsample,
-            )?;
-        }
-        NativePrimitiveType::UnsignedInteger64b => {
-            read_and_process_dataset_nonfloats::<i64>(
-                &dataset,
-                plot_args.axis,
-                plot_args.subsample,
-            )?;
-        }
-        NativePrimitiveType::Pointer(_) => {
-            read_and_process_dataset_nonfloats::<usize>(
-                &dataset,
-                plot_args.axis,
-                plot_args.subsample,
-            )?;
-        }
-        NativePrimitiveType::Float32b => {
-            read_and_process_dataset_floats::<f32>(&dataset, plot_args.axis, plot_args.subsample)?;
+    let mut series = vec![];
+    if let Some(name) = &plot_args.dataset_name {
+        let dataset = file.dataset(name)?;
+        let dtype = dataset.dtype()?;
+        let Some(native) = NativePrimitiveType::try_from_dtype(&dtype) else {
+            bail!("{name} is not numeric, its type is {}", type_name(&dtype));
+        };
+        let packing = match plot_args.raw {
+            true => None,
+            false => Packing::from_dataset(&dataset)?,
+        };
+
+        series.push(match &alignment {
+            Some(alignment) => {
+                resample_dataset(&file, &dataset, packing.as_ref(), alignment, plot_args)?
+            }
+            None => with_native_type!(
+                native,
+                T,
+                read_and_process_dataset::<T>(
+                    &dataset,
+                    packing.as_ref(),
+                    x_axis.as_ref(),
+                    plot_args,
+                )?
+            ),
+        });
+    }
+    for expr in &plot_args.exprs {
+        series.push(evaluate_expression(
+            &file,
+            expr,
+            x_axis.as_ref(),
+            alignment.as_ref(),
+            plot_args,
+        )?);
+    }
+
+    let raw = match plot_args.show_raw {
+        true => series.clone(),
+        false => vec![],
+    };
+    // Only every N'th sample is plotted, which lowers the sample rate of the series. Resampled
+    // series have the rate of the grid
+    let sample_rate = plot_args
+        .sample_rate
+        .or(alignment.as_ref().map(|a| 1.0 / a.grid.period))
+        .map(|rate| rate / plot_args.subsample as f64);
+    for s in &mut series {
+        for filter in &plot_args.filters {
+            s.values = filter.apply(&s.values, sample_rate, plot_args.filter_order)?;
+            s.name = format!("{} ({filter})", s.name);
         }
-        NativePrimitiveType::Float64b => {
-            read_and_process_dataset_floats::<f32>(&dataset, plot_args.axis, plot_args.subsample)?;
+    }
+
+    // The overlays are computed from the first (filtered) series
+    let first = &series[0];
+    let mut overlays = Overlays::default();
+    if let Some(window) = plot_args.rolling {
+        overlays.rolling = Some(Rolling::new(&first.values, window));
+        overlays.x = first.x.clone();
+    }
+    if plot_args.highlight.is_set() {
+        let mut detector = EventDetector::new(&plot_args.highlight);
+        for (i, &value) in first.values.iter().enumerate() {
+            detector.push(i, first.x.as_ref().map(|x| x[i]), value);
         }
+        overlays.events = detector.finish();
     }
 
+    let x_title = match &alignment {
+        Some(_) => "Time [s]",
+        None => plot_args.x_axis.as_deref().unwrap_or("Index"),
+    };
+    plot(raw.into_iter().chain(series).collect(), overlays, x_title)?;
     Ok(())
 }
 
-fn read_and_process_dataset_floats<T>(
+/// A named series of plotted values, NaN and infinite values are drawn as gaps
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub name: String,
+    /// Positions on the x-axis, the index of each value if `None`
+    pub x: Option<Vec<f64>>,
+    pub values: Vec<f64>,
+}
+
+/// Read the dataset block by block, keeping only every N'th sample for the plot and
+/// accumulating the statistics of those samples on the way
+///
+/// Samples of packed datasets are unpacked before they are folded, transformed, sampled and
+/// accumulated.
+fn read_and_process_dataset<T>(
     dataset: &Dataset,
-    axis: usize,
-    nth_sample: usize,
-) -> anyhow::Result<()>
+    packing: Option<&Packing>,
+    x_axis: Option<&Dataset>,
+    plot_args: &PlotArgs,
+) -> anyhow::Result<Series>
 where
-    T: H5Type
-        + Serialize
-        + num_traits::identities::Zero
-        + FromPrimitive
-        + Clone
-        + Copy
-        + std::ops::Div
-        + Bounded
-        + PartialOrd
-        + Display
-        + Real
-        + for<'a> std::iter::Sum<&'a T>,
-    for<'a> &'a T: Add<T, Output = T>,
-    <T as Div>::Output: fmt::Display,
+    T: Sample,
+    T::Wide: Accumulate,
 {
-    let ndims = dataset.ndim();
+    let memory_budget = plot_args.memory_budget << 20;
+    let mut sampler = Sampler::new(plot_args);
+    let len = sample_count(dataset, plot_args.axis)?;
 
-    match ndims {
-        1 => {
-            // Read the dataset into a 1D ndarray
-            let data_1dim: Array1<T> = dataset.read_1d()?;
-            process_and_plot_floats(data_1dim.view(), nth_sample)?;
-        }
-        2 => {
-            // Read the dataset into a 2D ndarray
-            let data_2dim: Array2<T> = dataset.read_2d()?;
-            let data_2dim_folded = data_2dim.fold_axis(Axis(axis), T::zero(), |acc, &x| acc + x);
-            process_and_plot_floats(data_2dim_folded.view(), nth_sample)?;
-        }
-        _ => {
-            bail!("Unsupported dataset dimensionality: {ndims}");
-        }
+    match packing {
+        Some(packing) => for_each_mapped::<T, _, _, _>(
+            dataset,
+            plot_args.axis,
+            memory_budget,
+            |x| packing.unpack_sample(x),
+            transformed(
+                plot_args.transform,
+                x_axis,
+                len,
+                memory_budget,
+                |i, t, y| sampler.push(i, t, y),
+            )?,
+        )?,
+        None => for_each_sample::<T, _>(
+            dataset,
+            plot_args.axis,
+            memory_budget,
+            transformed(
+                plot_args.transform,
+                x_axis,
+                len,
+                memory_budget,
+                |i, t, y| sampler.push(i, t, y),
+            )?,
+        )?,
     }
-    Ok(())
+
+    Ok(sampler.finish(dataset.name(), x_axis.is_some()))
 }
 
-fn read_and_process_dataset_nonfloats<T>(
+/// Resample the dataset onto the grid of the alignment, keeping only every N'th sample for the
+/// plot and accumulating the statistics of those samples on the way
+///
+/// The samples are read as floats, the transform is applied to the resampled series.
+fn resample_dataset(
+    file: &hdf5::File,
     dataset: &Dataset,
-    axis: usize,
-    nth_sample: usize,
-) -> anyhow::Result<()>
-where
-    T: H5Type
-        + Serialize
-        + num_traits::identities::Zero
-        + FromPrimitive
-        + ToPrimitive
-        + Clone
-        + Copy
-        + std::ops::Div
-        + Bounded
-        + PartialOrd
-        + Display
-        + Ord
-        + for<'a> std::iter::Sum<&'a T>
-        + std::ops::Sub<<T as std::ops::Div>::Output>,
-    for<'a> &'a T: Add<T, Output = T>,
-    <T as Div>::Output: fmt::Display + ToPrimitive,
-{
-    let ndims = dataset.ndim();
+    packing: Option<&Packing>,
+    alignment: &Alignment,
+    plot_args: &PlotArgs,
+) -> anyhow::Result<Series> {
+    let memory_budget = plot_args.memory_budget << 20;
+    let mut sampler = Sampler::new(plot_args);
 
-    match ndims {
-        1 => {
-            // Read the dataset into a 1D ndarray
-            let data_1dim: Array1<T> = dataset.read_1d()?;
-            process_and_plot_nonfloats(data_1dim.view(), nth_sample)?;
-        }
-        2 => {
-            // Read the dataset into a 2D ndarray
-            let data_2dim: Array2<T> = dataset.read_2d()?;
-            let data_2dim_folded = data_2dim.fold_axis(Axis(axis), T::zero(), |acc, &x| acc + x);
-            process_and_plot_nonfloats(data_2dim_folded.view(), nth_sample)?;
+    alignment
+        .resample_dataset(
+            file,
+            dataset,
+            packing,
+            plot_args.axis,
+            memory_budget,
+            positioned(plot_args.transform, |k, t, y| sampler.push(k, t, y)),
+        )
+        .with_context(|| format!("Failed resampling {}", dataset.name()))?;
+
+    Ok(sampler.finish(dataset.name(), true))
+}
+
+/// Evaluate the expression block by block, keeping only every N'th value for the plot and
+/// accumulating the statistics of those values on the way
+///
+/// With an alignment, the expression is evaluated over the resampled datasets.
+fn evaluate_expression(
+    file: &hdf5::File,
+    expr: &Expression,
+    x_axis: Option<&Dataset>,
+    alignment: Option<&Alignment>,
+    plot_args: &PlotArgs,
+) -> anyhow::Result<Series> {
+    let memory_budget = plot_args.memory_budget << 20;
+    let mut sampler = Sampler::new(plot_args);
+
+    match alignment {
+        Some(alignment) => {
+            let grid = alignment.grid;
+            let mut sink = positioned(plot_args.transform, |k, t, y| sampler.push(k, t, y));
+            expr.for_each_aligned_value(file, !plot_args.raw, alignment, memory_budget, |k, x| {
+                sink(k, grid.time(k), x)
+            })
         }
-        _ => {
-            bail!("Unsupported dataset dimensionality: {ndims}");
+        None => {
+            let len = expr.series_len(file)?;
+            let sink = transformed(
+                plot_args.transform,
+                x_axis,
+                len,
+                memory_budget,
+                |i, t, y| sampler.push(i, t, y),
+            )?;
+            expr.for_each_value(file, !plot_args.raw, memory_budget, sink)
         }
     }
+    .with_context(|| format!("Failed evaluating {}", expr.name))?;
 
-    Ok(())
+    Ok(sampler.finish(expr.name.clone(), x_axis.is_some() || alignment.is_some()))
 }
 
-fn process_and_plot_floats<T, D>(data: ArrayBase<D, Ix1>, nth_sample: usize) -> anyhow::Result<()>
-where
-    T: Zero
-        + FromPrimitive
-        + Copy
-        + Bounded
-        + PartialOrd
-        + Display
-        + serde::Serialize
-        + std::ops::Div
-        + Real
-        + 'static
-        + for<'a> std::iter::Sum<&'a T>,
-    D: Data<Elem = T>,
-{
-    let sampled_data: Vec<T> = data.iter().step_by(nth_sample).cloned().collect();
-    // Calculate statistics
-    let sum = sampled_data.iter().sum::<T>();
-    let min = sampled_data
-        .iter()
-        .fold(<T as Bounded>::max_value(), |a, &b| a.min(b));
-    let max = sampled_data
-        .iter()
-        .fold(<T as Bounded>::min_value(), |a, &b| a.max(b));
-    let avg = sum / T::from_usize(sampled_data.len()).unwrap();
-    let len = sampled_data.len();
+/// Every N'th sample of a series with its position on the x-axis, and the statistics of those
+/// samples
+struct Sampler {
+    nth_sample: usize,
+    transform: Option<Transform>,
+    x: Vec<f64>,
+    values: Vec<f64>,
+    acc: StatsAccumulator,
+}
 
-    // Calculate spread and deviation
-    let variance = sampled_data
-        .iter()
-        .fold(T::zero(), |acc, &x| acc + (x - avg) * (x - avg))
-        / T::from_usize(len).unwrap();
-    let std_dev = variance.sqrt();
+impl Sampler {
+    fn new(plot_args: &PlotArgs) -> Self {
+        Self {
+            nth_sample: plot_args.subsample,
+            transform: plot_args.transform,
+            x: vec![],
+            values: vec![],
+            acc: StatsAccumulator::moments_only().with_nan_policy(plot_args.nan_policy),
+        }
+    }
 
-    log::info!("Length={len}, Sum={sum:.4}, Avg={avg:.4}, Min={min:.4}, Max={max:.4}, σ={std_dev:.4}, σ²={variance:.4}");
+    fn push<W>(&mut self, i: usize, t: f64, y: Transformed<W>) -> anyhow::Result<()>
+    where
+        W: Accumulate + ToPrimitive,
+    {
+        if !i.is_multiple_of(self.nth_sample) {
+            return Ok(());
+        }
+        self.x.push(t);
+        self.values.push(y.to_f64());
+        y.accumulate(&mut self.acc)
+    }
 
-    plot(sampled_data)?;
-    Ok(())
+    /// Log the statistics and make the series, named after the transform if there is one
+    fn finish(self, name: String, with_x: bool) -> Series {
+        let name = match self.transform {
+            Some(transform) => format!("{name} ({transform})"),
+            None => name,
+        };
+        log_statistics(&name, self.acc.finish(), self.values.len());
+        Series {
+            name,
+            x: with_x.then_some(self.x),
+            values: self.values,
+        }
+    }
 }
 
-fn process_and_plot_nonfloats<T, D>(
-    data: ArrayBase<D, Ix1>,
-    nth_sample: usize,
-) -> anyhow::Result<()>
-where
-    T: Zero
-        + FromPrimitive
-        + ToPrimitive
-        + Copy
-        + Bounded
-        + PartialOrd
-        + Display
-        + serde::Serialize
-        + std::ops::Div
-        + Ord
-        + 'static
-        + for<'a> std::iter::Sum<&'a T>
-        + std::ops::Sub<<T as std::ops::Div>::Output>,
-    D: Data<Elem = T>,
-    <T as Div>::Output: std::fmt::Display,
-    <T as Div>::Output: ToPrimitive,
-{
-    let sampled_data: Vec<T> = data.iter().step_by(nth_sample).cloned().collect();
-    // Calculate statistics
-    let sum = sampled_data.iter().sum::<T>();
-    let min = sampled_data
-        .iter()
-        .fold(<T as Bounded>::max_value(), |a, &b| a.min(b));
-    let max = sampled_data
-        .iter()
-        .fold(<T as Bounded>::min_value(), |a, &b| a.max(b));
-    let avg = sum / T::from_usize(sampled_data.len()).unwrap();
-    let len = sampled_data.len();
-
-    // Calculate variance and standard deviation
-    let variance = sampled_data.iter().fold(T::zero(), |acc, &x| {
-        let diff = x.to_f64().unwrap() - avg.to_f64().unwrap();
-        acc + T::from_f64(diff * diff).unwrap()
-    }) / T::from_usize(len).unwrap();
-    let std_dev = variance.to_f64().unwrap().sqrt();
+fn log_statistics(name: &str, stats: Statistics, len: usize) {
+    stats.warn_non_finite(name);
+    let sum = match stats.integer_sum {
+        Some(sum) => sum.to_string(),
+        None => format!("{:.4}", stats.sum),
+    };
+    let avg = stats.mean;
+    let min = stats.min.map_or(f64::NAN, |m| m.value);
+    let max = stats.max.map_or(f64::NAN, |m| m.value);
+    let std_dev = stats.std_dev;
+    let variance = std_dev * std_dev;
 
-    log::info!("Length={len}, Sum={sum:.4}, Avg={avg:.4}, Min={min:.4}, Max={max:.4}, σ={std_dev:.4}, σ²={variance:.4}");
+    log::info!("{name}: Length={len}, Sum={sum}, Avg={avg:.4}, Min={min:.4}, Max={max:.4}, σ={std_dev:.4}, σ²={variance:.4}");
+}
 
-    plot(sampled_data)?;
-    Ok(())
+/// Extra traces drawn on top of the data
+#[derive(Debug, Default)]
+pub struct Overlays {
+    pub rolling: Option<Rolling>,
+    /// Positions on the x-axis of the overlays, the index of each value if `None`
+    pub x: Option<Vec<f64>>,
+    /// Excursions of the first series, highlighted in the background
+    pub events: Vec<Event>,
 }
 
-pub fn plot<T>(data: Vec<T>) -> anyhow::Result<()>
-where
-    T: Serialize + Clone + 'static,
-{
+pub fn plot(series: Vec<Series>, overlays: Overlays, x_title: &str) -> anyhow::Result<()> {
     // Plot the data
     #[cfg(features = "rplotters")]
     rplotters::plot_data(dxxx.as_slice().unwrap(), dxxx.len(), 1, min, max)?;
 
     #[cfg(feature = "rplotly")]
-    rplotly::plotly(data);
+    rplotly::plotly(series, overlays, x_title);
 
     Ok(())
 }
@@ -1,27 +1,48 @@
-use serde::Serialize;
+use plotly::{
+    color::Rgba,
+    common::{DashType, Fill, Line, Mode},
+    layout::{Shape, ShapeLayer, ShapeLine, ShapeType},
+    Plot, Scatter,
+};
 use std::fs;
 
-pub(crate) fn plotly<T>(data: Vec<T>)
-where
-    T: Serialize + Clone + 'static,
-{
-    use plotly::layout::{Axis, Layout};
-    use plotly::Plot;
-    use plotly::Scatter;
-    let x_values: Vec<_> = (0..data.len()).collect();
+use super::{Overlays, Series};
+use crate::{
+    events::{Event, Excursion},
+    stats::rolling::Rolling,
+};
 
-    // Create a plot
-    let trace = Scatter::new(x_values, data)
-        .mode(plotly::common::Mode::Lines)
-        .name("Data");
+pub(crate) fn plotly(series: Vec<Series>, overlays: Overlays, x_title: &str) {
+    use plotly::layout::{Axis, Layout};
 
-    let layout = Layout::new()
+    let mut layout = Layout::new()
         .title("Interactive HDF5 Data Plot")
-        .x_axis(Axis::new().title("Index"))
+        .x_axis(Axis::new().title(x_title))
         .y_axis(Axis::new().title("Value"));
 
     let mut plot = Plot::new();
-    plot.add_trace(trace);
+    for Series { name, x, values } in series {
+        let x_values = x.unwrap_or_else(|| index_positions(values.len()));
+        // Non-finite values become gaps in the trace
+        let values: Vec<Option<f64>> = values
+            .into_iter()
+            .map(|x| x.is_finite().then_some(x))
+            .collect();
+        plot.add_trace(
+            Scatter::new(x_values, values)
+                .mode(plotly::common::Mode::Lines)
+                .name(&name),
+        );
+    }
+    if let Some(rolling) = &overlays.rolling {
+        let x_values = overlays
+            .x
+            .unwrap_or_else(|| index_positions(rolling.mean.len()));
+        add_rolling_traces(&mut plot, rolling, x_values);
+    }
+    for event in &overlays.events {
+        layout.add_shape(event_shape(event));
+    }
     plot.set_layout(layout);
 
     // Save the plot as an HTML file
@@ -41,3 +62,74 @@ where
         "Plot saved to plot.html. Open this file in a web browser to view the interactive plot."
     );
 }
+
+/// Add the rolling mean with a shaded ±σ band, and dotted min/max envelopes
+fn add_rolling_traces(plot: &mut Plot, rolling: &Rolling, x_values: Vec<f64>) {
+    let window = rolling.window;
+    let offset_by_std_dev = |sign: f64| -> Vec<Option<f64>> {
+        rolling
+            .mean
+            .iter()
+            .zip(&rolling.std_dev)
+            .map(|(mean, std_dev)| Some(mean.as_ref()? + sign * std_dev.as_ref()?))
+            .collect()
+    };
+
+    // The band is filled from the lower bound to the upper bound, which must be the previous trace
+    plot.add_trace(
+        Scatter::new(x_values.clone(), offset_by_std_dev(1.0))
+            .mode(Mode::Lines)
+            .line(Line::new().width(0.0))
+            .show_legend(false)
+            .name(&format!("Rolling mean + σ ({window})")),
+    );
+    plot.add_trace(
+        Scatter::new(x_values.clone(), offset_by_std_dev(-1.0))
+            .mode(Mode::Lines)
+            .line(Line::new().width(0.0))
+            .fill(Fill::ToNextY)
+            .fill_color(Rgba::new(255, 127, 14, 0.25))
+            .name(&format!("Rolling ±σ ({window})")),
+    );
+    plot.add_trace(
+        Scatter::new(x_values.clone(), rolling.mean.clone())
+            .mode(Mode::Lines)
+            .line(Line::new().color(Rgba::new(255, 127, 14, 1.0)))
+            .name(&format!("Rolling mean ({window})")),
+    );
+    for (name, envelope) in [("min", &rolling.min), ("max", &rolling.max)] {
+        plot.add_trace(
+            Scatter::new(x_values.clone(), envelope.clone())
+                .mode(Mode::Lines)
+                .line(
+                    Line::new()
+                        .dash(DashType::Dot)
+                        .color(Rgba::new(44, 160, 44, 1.0)),
+                )
+                .name(&format!("Rolling {name} ({window})")),
+        );
+    }
+}
+
+/// Background band over the full height of the plot, red for excursions above and blue below
+fn event_shape(event: &Event) -> Shape {
+    let color = match event.kind {
+        Excursion::Above => Rgba::new(214, 39, 40, 0.15),
+        Excursion::Below => Rgba::new(31, 119, 180, 0.15),
+    };
+    Shape::new()
+        .shape_type(ShapeType::Rect)
+        .layer(ShapeLayer::Below)
+        .x_ref("x")
+        .y_ref("paper")
+        .x0(event.start.unwrap_or(event.start_index as f64))
+        .x1(event.end.unwrap_or(event.end_index as f64))
+        .y0(0.0)
+        .y1(1.0)
+        .fill_color(color)
+        .line(ShapeLine::new().width(0.0))
+}
+
+fn index_positions(len: usize) -> Vec<f64> {
+    (0..len).map(|i| i as f64).collect()
+}
@@ -0,0 +1,308 @@
+//! Resampling of series with their own timestamps onto a common time grid
+//!
+//! Datasets recorded at different rates are paired with the datasets of their timestamps, and
+//! their values are interpolated at the times of a grid with a fixed period. The grid spans the
+//! time range covered by all of the series, so the resampled series have the same length and can
+//! be overlaid and combined element-wise.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use hdf5::{Dataset, Hyperslab, Selection, SliceOrIndex};
+
+use crate::{
+    config::{misc::Interpolation, Resampling},
+    my_hdf5::{
+        cf::Packing,
+        chunked::{elements, for_each_mapped},
+    },
+};
+
+/// Parse a period such as `1ms`, `50us`, `0.5s` or `100ns`, in seconds
+pub fn parse_period(s: &str) -> anyhow::Result<f64> {
+    let units = [
+        ("ns", 1e-9),
+        ("us", 1e-6),
+        ("µs", 1e-6),
+        ("ms", 1e-3),
+        ("s", 1.0),
+    ];
+    let (number, scale) = units
+        .iter()
+        .find_map(|&(unit, scale)| s.strip_suffix(unit).map(|number| (number, scale)))
+        .with_context(|| format!("Invalid period '{s}', expected e.g. '1ms', '50us' or '0.5s'"))?;
+    match number.trim().parse::<f64>() {
+        Ok(period) if period > 0.0 && period.is_finite() => Ok(period * scale),
+        _ => bail!("Invalid period '{s}', expected a positive duration like '1ms'"),
+    }
+}
+
+/// Parse a pair of a value dataset and its timestamps dataset, e.g. `/adc/ch1=/adc/time`
+pub fn parse_timestamps_pair(s: &str) -> anyhow::Result<(String, String)> {
+    match s.split_once('=') {
+        Some((dataset, timestamps)) if !dataset.is_empty() && !timestamps.is_empty() => {
+            Ok((absolute(dataset), absolute(timestamps)))
+        }
+        _ => bail!("Invalid pair '{s}', expected e.g. '/adc/ch1=/adc/time'"),
+    }
+}
+
+fn absolute(path: &str) -> String {
+    match path.starts_with('/') {
+        true => path.to_owned(),
+        false => format!("/{path}"),
+    }
+}
+
+/// Times `start + k * period` for `k` in `0..len`, in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    pub start: f64,
+    pub period: f64,
+    pub len: usize,
+}
+
+impl Grid {
+    pub fn time(&self, k: usize) -> f64 {
+        self.start + k as f64 * self.period
+    }
+}
+
+/// How the series of a command are resampled onto one grid
+#[derive(Debug, Clone)]
+pub struct Alignment {
+    pub grid: Grid,
+    interpolation: Interpolation,
+    /// Seconds per unit of the timestamps
+    time_scale: f64,
+    /// Timestamps dataset of each value dataset
+    pairs: HashMap<String, String>,
+    default_timestamps: Option<String>,
+}
+
+impl Alignment {
+    /// The alignment of `datasets` onto a grid over the time range they all cover, `None`
+    /// unless resampling is enabled
+    ///
+    /// Datasets are paired with their timestamps by `--timestamps`, or else with
+    /// `default_timestamps`, e.g. the x-axis dataset.
+    pub fn new(
+        file: &hdf5::File,
+        resampling: &Resampling,
+        datasets: &[&str],
+        default_timestamps: Option<&str>,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(period) = resampling.resample else {
+            if !resampling.timestamps.is_empty() {
+                log::warn!("Ignoring --timestamps without --resample");
+            }
+            return Ok(None);
+        };
+        let mut alignment = Self {
+            grid: Grid {
+                start: f64::NEG_INFINITY,
+                period,
+                len: 0,
+            },
+            interpolation: resampling.interpolation,
+            time_scale: resampling.time_unit.scale(),
+            pairs: resampling.timestamps.iter().cloned().collect(),
+            default_timestamps: default_timestamps.map(absolute),
+        };
+
+        if datasets.is_empty() {
+            bail!("Nothing to resample");
+        }
+        let mut end = f64::INFINITY;
+        for dataset in datasets {
+            let timestamps = alignment.timestamps(file, dataset)?;
+            let (first, last) = alignment.time_range(&timestamps)?;
+            log::debug!(
+                "{dataset} spans {first} s to {last} s by {}",
+                timestamps.name()
+            );
+            alignment.grid.start = alignment.grid.start.max(first);
+            end = end.min(last);
+        }
+        if end < alignment.grid.start {
+            bail!(
+                "The series do not overlap in time, the latest starts at {} s and the earliest ends at {end} s",
+                alignment.grid.start
+            );
+        }
+        alignment.grid.len = ((end - alignment.grid.start) / period).floor() as usize + 1;
+        // Rounding may put the last time of the grid just after the end, where not all of the
+        // series have samples to resample from
+        while alignment.grid.len > 1 && alignment.grid.time(alignment.grid.len - 1) > end {
+            alignment.grid.len -= 1;
+        }
+        log::info!(
+            "Resampling onto {} times from {} s every {period} s ({})",
+            alignment.grid.len,
+            alignment.grid.start,
+            alignment.interpolation
+        );
+        Ok(Some(alignment))
+    }
+
+    /// The timestamps dataset paired with the dataset at `path`
+    pub fn timestamps(&self, file: &hdf5::File, path: &str) -> anyhow::Result<Dataset> {
+        let path = absolute(path);
+        let timestamps = match self.pairs.get(&path).or(self.default_timestamps.as_ref()) {
+            Some(timestamps) => file.dataset(timestamps)?,
+            None => bail!(
+                "No timestamps for {path}, pair it with e.g. '--timestamps {path}=/time' or pass --x-axis"
+            ),
+        };
+        if timestamps.ndim() != 1 {
+            bail!(
+                "The timestamps dataset {} must be 1-D, got a {}-D dataset",
+                timestamps.name(),
+                timestamps.ndim()
+            );
+        }
+        Ok(timestamps)
+    }
+
+    /// The first and last timestamp in seconds
+    fn time_range(&self, timestamps: &Dataset) -> anyhow::Result<(f64, f64)> {
+        let len = timestamps.shape()[0];
+        if len == 0 {
+            bail!("The timestamps dataset {} is empty", timestamps.name());
+        }
+        let at = |i: usize| -> anyhow::Result<f64> {
+            let selection = Selection::from(Hyperslab::from(vec![SliceOrIndex::from(i..i + 1)]));
+            let value = timestamps.read_slice_1d::<f64, _>(selection)?[0];
+            Ok(value * self.time_scale)
+        };
+        Ok((at(0)?, at(len - 1)?))
+    }
+
+    /// Wrap `f` to visit the values of a series at the times of the grid, as `f(k, t, value)`
+    ///
+    /// The samples of the series must be visited in order. Their times are read from the 1-D
+    /// `timestamps` dataset, in blocks of at most `memory_budget` bytes, and must not decrease.
+    pub fn resampling<'a, F>(
+        &self,
+        timestamps: &'a Dataset,
+        memory_budget: usize,
+        mut f: F,
+    ) -> anyhow::Result<impl FnMut(usize, f64) -> anyhow::Result<()> + 'a>
+    where
+        F: FnMut(usize, f64, f64) -> anyhow::Result<()> + 'a,
+    {
+        let mut times = elements::<f64>(timestamps, memory_budget);
+        let mut resampler = Resampler {
+            grid: self.grid,
+            interpolation: self.interpolation,
+            prev: None,
+            next: 0,
+        };
+        let time_scale = self.time_scale;
+        let name = timestamps.name();
+
+        Ok(move |_, value| {
+            let t = times.next().with_context(|| {
+                format!("The timestamps dataset {name} has fewer values than the series")
+            })??;
+            resampler.push(t * time_scale, value, &mut f)
+        })
+    }
+
+    /// Resample a 1-D dataset, or a 2-D dataset folded along `axis`, and visit its values at
+    /// the times of the grid, as `f(k, t, value)`
+    ///
+    /// The samples are read as floats and unpacked if the dataset is packed, at most
+    /// `memory_budget` bytes are read at a time.
+    pub fn resample_dataset<F>(
+        &self,
+        file: &hdf5::File,
+        dataset: &Dataset,
+        packing: Option<&Packing>,
+        axis: usize,
+        memory_budget: usize,
+        f: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(usize, f64, f64) -> anyhow::Result<()>,
+    {
+        let timestamps = self.timestamps(file, &dataset.name())?;
+        let shape = dataset.shape();
+        let len = match (shape.len(), axis) {
+            (2, 0) => shape[1],
+            _ => shape.first().copied().unwrap_or(0),
+        };
+        check_length(&timestamps, len, &dataset.name())?;
+
+        let sink = self.resampling(&timestamps, memory_budget / 2, f)?;
+        for_each_mapped::<f64, f64, _, _>(
+            dataset,
+            axis,
+            memory_budget / 2,
+            |x| packing.map_or(x, |packing| packing.unpack(x)),
+            sink,
+        )
+    }
+}
+
+/// Check that there is one timestamp per sample of a series of length `len`
+pub fn check_length(timestamps: &Dataset, len: usize, series: &str) -> anyhow::Result<()> {
+    let times = timestamps.shape()[0];
+    if times != len {
+        bail!(
+            "{series} has {len} samples but its timestamps dataset {} has {times} values",
+            timestamps.name()
+        );
+    }
+    Ok(())
+}
+
+/// Streaming interpolation of one series at the times of the grid
+#[derive(Debug, Clone)]
+struct Resampler {
+    grid: Grid,
+    interpolation: Interpolation,
+    /// Time and value of the previous sample
+    prev: Option<(f64, f64)>,
+    /// Index of the next time of the grid
+    next: usize,
+}
+
+impl Resampler {
+    /// Add the next sample at time `t`, and visit the values at the times of the grid up to `t`
+    fn push<F>(&mut self, t: f64, value: f64, f: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(usize, f64, f64) -> anyhow::Result<()>,
+    {
+        if !t.is_finite() {
+            bail!("Invalid timestamp {t}");
+        }
+        if let Some((prev_t, _)) = self.prev {
+            if t < prev_t {
+                bail!("The timestamps must not decrease, got {t} s after {prev_t} s");
+            }
+        }
+
+        while self.next < self.grid.len {
+            let time = self.grid.time(self.next);
+            if time > t {
+                break;
+            }
+            let resampled = match self.prev {
+                Some((prev_t, prev)) if time < t => match self.interpolation {
+                    Interpolation::Nearest if time - prev_t <= t - time => prev,
+                    Interpolation::Nearest => value,
+                    Interpolation::Linear => prev + (value - prev) * (time - prev_t) / (t - prev_t),
+                    Interpolation::Zoh => prev,
+                },
+                // The series starts after this time of the grid
+                None if time < t => f64::NAN,
+                _ => value,
+            };
+            f(self.next, time, resampled)?;
+            self.next += 1;
+        }
+        self.prev = Some((t, value));
+        Ok(())
+    }
+}
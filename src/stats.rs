@@ -0,0 +1,566 @@
+pub mod accumulator;
+pub mod quantile;
+pub mod report;
+pub mod rolling;
+pub mod transform;
+
+use anyhow::{bail, Context};
+use hdf5::Dataset;
+use ndarray::Ix2;
+use termcolor::{Color, StandardStream};
+
+use crate::{
+    config::{
+        misc::{NanPolicy, StatsFormat, Transform},
+        Config, StatsArgs,
+    },
+    my_hdf5::{
+        cf::Packing,
+        chunked::{for_each_mapped, for_each_sample, sample_count, RowBlocks, Sample},
+        util::{type_name, with_native_type, NativePrimitiveType},
+    },
+    resample::Alignment,
+    util::{print_colored_quoted, print_table},
+};
+
+use accumulator::{Accumulate, StatsAccumulator};
+use report::{print_table_of_records, StatsRecord};
+use transform::{positioned, transformed, Transformed};
+
+pub fn handle_stats_cmd(args: &StatsArgs, cfg: &Config) -> anyhow::Result<()> {
+    log::trace!("{args:?}");
+    if let Some(p) = args
+        .percentiles
+        .iter()
+        .find(|p| !(0.0..=100.0).contains(*p))
+    {
+        bail!("Invalid percentile: {p}, expected a value in the range 0-100");
+    }
+
+    log::debug!("opening: {:?}", args.src_hdf5.as_path());
+    let file = hdf5::File::open(args.src_hdf5.as_path())?;
+
+    // Half of the budget is for reading blocks of the dataset, the other half for buffering
+    // values to compute exact quantiles
+    let memory_budget = args.memory_budget << 20;
+    let mut stdout = StandardStream::stdout(cfg.color_when());
+    let mut records = vec![];
+    let x_axis = match &args.x_axis {
+        Some(name) => Some(file.dataset(name)?),
+        None => None,
+    };
+
+    // With resampling, the datasets and the datasets of all expressions are aligned onto one
+    // grid, and are resampled as 64-bit floats
+    let refs: Vec<&str> = args
+        .datasets
+        .iter()
+        .map(String::as_str)
+        .chain(
+            args.exprs
+                .iter()
+                .flat_map(|e| e.refs())
+                .map(|r| r.path.as_str()),
+        )
+        .collect();
+    let alignment = Alignment::new(&file, &args.resampling, &refs, args.x_axis.as_deref())?;
+
+    for name in &args.datasets {
+        let dataset = file.dataset(name)?;
+        let packing = match args.raw {
+            true => None,
+            false => Packing::from_dataset(&dataset)?,
+        };
+        let source = match &packing {
+            Some(packing) => packing.unpacked_type,
+            None => {
+                let dtype = dataset.dtype()?;
+                let Some(native) = NativePrimitiveType::try_from_dtype(&dtype) else {
+                    bail!("{name} is not numeric, its type is {}", type_name(&dtype));
+                };
+                native
+            }
+        };
+        // Differences and sums leave the range of integer types, the other transforms and
+        // resampling yield floats
+        let dtype = match (args.transform, &alignment) {
+            (None, None) => source,
+            _ => NativePrimitiveType::Float64b,
+        };
+        let mut shape = dataset.shape();
+        let lanes = match (args.lanes, &alignment) {
+            (_, Some(alignment)) => {
+                let mut acc = StatsAccumulator::new(&args.percentiles, memory_budget / 2)
+                    .with_nan_policy(args.nan_policy);
+                shape = vec![alignment.grid.len];
+                let sink = positioned(args.transform, |_, _, y: Transformed<f64>| {
+                    y.accumulate(&mut acc)
+                });
+                alignment
+                    .resample_dataset(
+                        &file,
+                        &dataset,
+                        packing.as_ref(),
+                        args.axis,
+                        memory_budget / 2,
+                        sink,
+                    )
+                    .map(|()| vec![acc.finish()])
+            }
+            (Some(lane_axis), None) => lane_statistics(
+                &dataset,
+                packing.as_ref(),
+                lane_axis,
+                &args.percentiles,
+                args.nan_policy,
+                memory_budget,
+            ),
+            (None, None) => {
+                let mut acc = StatsAccumulator::new(&args.percentiles, memory_budget / 2)
+                    .with_nan_policy(args.nan_policy);
+                accumulate_dataset(
+                    &dataset,
+                    packing.as_ref(),
+                    args.transform,
+                    x_axis.as_ref(),
+                    args.axis,
+                    memory_budget / 2,
+                    &mut acc,
+                )
+                .map(|()| vec![acc.finish()])
+            }
+        }
+        .with_context(|| format!("Failed computing statistics of {name}"))?;
+        for stats in &lanes {
+            stats.warn_non_finite(name);
+        }
+
+        match (args.format, args.lanes) {
+            (StatsFormat::Text, Some(_)) => {
+                print_colored_quoted(&mut stdout, Color::Cyan, "Dataset:", dataset.name())?;
+                print_lane_table(&lanes, &args.percentiles, &mut stdout)?;
+            }
+            (StatsFormat::Text, None) => {
+                print_statistics(&dataset.name(), dtype, &shape, &lanes[0], &mut stdout)?
+            }
+            _ => {
+                for (i, stats) in lanes.into_iter().enumerate() {
+                    records.push(StatsRecord::new(
+                        &dataset,
+                        dtype,
+                        shape.clone(),
+                        args.lanes.map(|_| i),
+                        stats,
+                    ));
+                }
+            }
+        }
+    }
+
+    // Expressions are evaluated as 64-bit floats and always yield a 1-D series, the length is
+    // that of the transformed series
+    for expr in &args.exprs {
+        let mut acc = StatsAccumulator::new(&args.percentiles, memory_budget / 2)
+            .with_nan_policy(args.nan_policy);
+        let mut len = 0;
+        let accumulate = |_: usize, _: f64, y: Transformed<f64>| {
+            len += 1;
+            y.accumulate(&mut acc)
+        };
+        match &alignment {
+            Some(alignment) => {
+                let grid = alignment.grid;
+                let mut sink = positioned(args.transform, accumulate);
+                expr.for_each_aligned_value(
+                    &file,
+                    !args.raw,
+                    alignment,
+                    memory_budget / 2,
+                    |k, x| sink(k, grid.time(k), x),
+                )
+            }
+            None => {
+                let sink = transformed(
+                    args.transform,
+                    x_axis.as_ref(),
+                    expr.series_len(&file)?,
+                    memory_budget / 2,
+                    accumulate,
+                )?;
+                expr.for_each_value(&file, !args.raw, memory_budget / 2, sink)
+            }
+        }
+        .with_context(|| format!("Failed computing statistics of {}", expr.name))?;
+        let stats = acc.finish();
+        stats.warn_non_finite(&expr.name);
+
+        let dtype = NativePrimitiveType::Float64b;
+        match args.format {
+            StatsFormat::Text => print_statistics(&expr.name, dtype, &[len], &stats, &mut stdout)?,
+            _ => records.push(StatsRecord {
+                dataset: expr.name.clone(),
+                dtype: dtype.name(),
+                shape: vec![len],
+                lane: None,
+                statistics: stats,
+            }),
+        }
+    }
+
+    match args.format {
+        StatsFormat::Text => (),
+        StatsFormat::Table => print_table_of_records(&mut stdout, &records, &args.percentiles)?,
+        StatsFormat::Json => report::write_json(&mut stdout, &records)?,
+        StatsFormat::Csv => report::write_csv(&mut stdout, &records, &args.percentiles)?,
+    }
+
+    Ok(())
+}
+
+/// Accumulate the samples of a 1-D dataset, or of a 2-D dataset folded along `axis`,
+/// reading integers as integers so that their sum is exact, unless they are unpacked
+///
+/// The samples are transformed before they are accumulated, with the positions on the x-axis
+/// read from `x_axis`, see [`transformed`].
+pub fn accumulate_dataset(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    transform: Option<Transform>,
+    x_axis: Option<&Dataset>,
+    axis: usize,
+    memory_budget: usize,
+    acc: &mut StatsAccumulator,
+) -> anyhow::Result<()> {
+    let dtype = dataset.dtype()?;
+    let Some(native) = NativePrimitiveType::try_from_dtype(&dtype) else {
+        bail!(
+            "{} is not numeric, its type is {}",
+            dataset.name(),
+            type_name(&dtype)
+        );
+    };
+    with_native_type!(
+        native,
+        T,
+        accumulate_samples::<T>(
+            dataset,
+            packing,
+            transform,
+            x_axis,
+            axis,
+            memory_budget,
+            acc,
+        )
+    )
+}
+
+fn accumulate_samples<T>(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    transform: Option<Transform>,
+    x_axis: Option<&Dataset>,
+    axis: usize,
+    memory_budget: usize,
+    acc: &mut StatsAccumulator,
+) -> anyhow::Result<()>
+where
+    T: Sample,
+    T::Wide: Accumulate,
+{
+    let len = sample_count(dataset, axis)?;
+    match packing {
+        Some(packing) => for_each_mapped::<T, _, _, _>(
+            dataset,
+            axis,
+            memory_budget,
+            |x| packing.unpack_sample(x),
+            transformed(transform, x_axis, len, memory_budget, |_, _, y| {
+                y.accumulate(acc)
+            })?,
+        ),
+        None => for_each_sample::<T, _>(
+            dataset,
+            axis,
+            memory_budget,
+            transformed(transform, x_axis, len, memory_budget, |_, _, y| {
+                y.accumulate(acc)
+            })?,
+        ),
+    }
+}
+
+/// Statistics of every lane along `axis` of a 2-D dataset, e.g. of every column for axis 0
+pub fn lane_statistics(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    axis: usize,
+    percentiles: &[f64],
+    nan_policy: NanPolicy,
+    memory_budget: usize,
+) -> anyhow::Result<Vec<Statistics>> {
+    let dtype = dataset.dtype()?;
+    let Some(native) = NativePrimitiveType::try_from_dtype(&dtype) else {
+        bail!(
+            "{} is not numeric, its type is {}",
+            dataset.name(),
+            type_name(&dtype)
+        );
+    };
+    with_native_type!(
+        native,
+        T,
+        lane_samples::<T>(
+            dataset,
+            packing,
+            axis,
+            percentiles,
+            nan_policy,
+            memory_budget,
+        )
+    )
+}
+
+fn lane_samples<T>(
+    dataset: &Dataset,
+    packing: Option<&Packing>,
+    axis: usize,
+    percentiles: &[f64],
+    nan_policy: NanPolicy,
+    memory_budget: usize,
+) -> anyhow::Result<Vec<Statistics>>
+where
+    T: Sample,
+    T::Wide: Accumulate,
+{
+    let shape = dataset.shape();
+    if shape.len() != 2 {
+        bail!(
+            "Statistics per lane require a 2-D dataset, got a {}-D dataset",
+            shape.len()
+        );
+    }
+    let blocks = RowBlocks::<T>::new(dataset, memory_budget / 2);
+    let accumulate = |x: T, acc: &mut StatsAccumulator| match packing {
+        Some(packing) => acc.push(packing.unpack_sample(x)),
+        None => x.widen().accumulate(acc),
+    };
+
+    match axis {
+        0 => {
+            // All columns are accumulated at once, so they share the budget for quantiles
+            let budget = memory_budget / 2 / shape[1].max(1);
+            let mut accs: Vec<StatsAccumulator> = (0..shape[1])
+                .map(|_| StatsAccumulator::new(percentiles, budget).with_nan_policy(nan_policy))
+                .collect();
+            for block in blocks {
+                let (_, block) = block?;
+                for row in block.into_dimensionality::<Ix2>()?.rows() {
+                    for (acc, &x) in accs.iter_mut().zip(row) {
+                        accumulate(x, acc)?;
+                    }
+                }
+            }
+            Ok(accs.into_iter().map(StatsAccumulator::finish).collect())
+        }
+        1 => {
+            let mut lanes = Vec::with_capacity(shape[0]);
+            for block in blocks {
+                let (_, block) = block?;
+                for row in block.into_dimensionality::<Ix2>()?.rows() {
+                    let mut acc = StatsAccumulator::new(percentiles, memory_budget / 2)
+                        .with_nan_policy(nan_policy);
+                    for &x in row {
+                        accumulate(x, &mut acc)?;
+                    }
+                    lanes.push(acc.finish());
+                }
+            }
+            Ok(lanes)
+        }
+        _ => bail!("Invalid axis {axis} for a 2-D dataset"),
+    }
+}
+
+/// A minimum or maximum value and the index where it first occurs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extremum {
+    pub index: usize,
+    pub value: f64,
+}
+
+/// Descriptive statistics of a series
+///
+/// NaN and infinite values are always counted, whether they are included in the remaining
+/// statistics depends on the [`NanPolicy`](crate::config::misc::NanPolicy).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statistics {
+    /// Number of values included in the statistics
+    pub count: usize,
+    pub nan_count: usize,
+    pub pos_inf_count: usize,
+    pub neg_inf_count: usize,
+    pub sum: f64,
+    /// Exact sum of integer datasets
+    pub integer_sum: Option<i128>,
+    pub mean: f64,
+    /// Population standard deviation
+    pub std_dev: f64,
+    pub min: Option<Extremum>,
+    pub max: Option<Extremum>,
+    pub median: f64,
+    /// Pairs of (percentile, value)
+    pub percentiles: Vec<(f64, f64)>,
+    /// Whether the median and percentiles are exact or approximated
+    pub quantiles_exact: bool,
+    pub skewness: f64,
+    /// Excess kurtosis, 0 for a normal distribution
+    pub kurtosis: f64,
+}
+
+impl Statistics {
+    /// Compute the statistics of `values`, `percentiles` are given in the range 0-100
+    pub fn from_values<I>(
+        values: I,
+        percentiles: &[f64],
+        nan_policy: NanPolicy,
+    ) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        let mut acc = StatsAccumulator::new(percentiles, usize::MAX).with_nan_policy(nan_policy);
+        values.into_iter().try_for_each(|x| acc.push(x))?;
+        Ok(acc.finish())
+    }
+
+    /// Column names of [`Statistics::table_row`]
+    pub fn table_header(percentiles: &[f64]) -> Vec<String> {
+        let mut header: Vec<String> = ["count", "NaN", "±inf", "mean", "σ", "min", "max", "median"]
+            .map(String::from)
+            .into();
+        header.extend(percentiles.iter().map(|p| format!("p{p}")));
+        header.extend(["skewness", "kurtosis"].map(String::from));
+        header
+    }
+
+    /// The statistics formatted as the cells of a table row
+    pub fn table_row(&self) -> Vec<String> {
+        let approx = if self.quantiles_exact { "" } else { "~" };
+        let mut row = vec![
+            self.count.to_string(),
+            self.nan_count.to_string(),
+            (self.pos_inf_count + self.neg_inf_count).to_string(),
+            format!("{:.4}", self.mean),
+            format!("{:.4}", self.std_dev),
+            self.min
+                .map_or("-".to_owned(), |m| format!("{:.4}", m.value)),
+            self.max
+                .map_or("-".to_owned(), |m| format!("{:.4}", m.value)),
+            format!("{approx}{:.4}", self.median),
+        ];
+        row.extend(
+            self.percentiles
+                .iter()
+                .map(|(_, value)| format!("{approx}{value:.4}")),
+        );
+        row.push(format!("{:.4}", self.skewness));
+        row.push(format!("{:.4}", self.kurtosis));
+        row
+    }
+
+    /// Log a warning if any NaN or infinite values were encountered
+    pub fn warn_non_finite(&self, name: &str) {
+        let non_finite = self.nan_count + self.pos_inf_count + self.neg_inf_count;
+        if non_finite > 0 {
+            log::warn!(
+                "{name} contains {} NaN, {} +inf and {} -inf values",
+                self.nan_count,
+                self.pos_inf_count,
+                self.neg_inf_count
+            );
+        }
+    }
+}
+
+fn print_lane_table(
+    lanes: &[Statistics],
+    percentiles: &[f64],
+    out: &mut StandardStream,
+) -> anyhow::Result<()> {
+    let mut header = vec!["lane".to_owned()];
+    header.extend(Statistics::table_header(percentiles));
+    let rows: Vec<Vec<String>> = lanes
+        .iter()
+        .enumerate()
+        .map(|(i, stats)| {
+            let mut row = vec![i.to_string()];
+            row.extend(stats.table_row());
+            row
+        })
+        .collect();
+    print_table(out, Color::Cyan, &header, &rows)
+}
+
+fn print_statistics(
+    name: &str,
+    dtype: NativePrimitiveType,
+    shape: &[usize],
+    stats: &Statistics,
+    out: &mut StandardStream,
+) -> anyhow::Result<()> {
+    print_colored_quoted(out, Color::Cyan, "Dataset:", name)?;
+    print_colored_quoted(out, Color::Yellow, "  - Data type: ", dtype)?;
+    print_colored_quoted(out, Color::Yellow, "  - Shape: ", format!("{shape:?}"))?;
+    print_colored_quoted(out, Color::Yellow, "  - Count: ", stats.count)?;
+    print_colored_quoted(out, Color::Yellow, "  - NaN count: ", stats.nan_count)?;
+    print_colored_quoted(out, Color::Yellow, "  - +inf count: ", stats.pos_inf_count)?;
+    print_colored_quoted(out, Color::Yellow, "  - -inf count: ", stats.neg_inf_count)?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Mean: ",
+        format!("{:.4}", stats.mean),
+    )?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - σ: ",
+        format!("{:.4}", stats.std_dev),
+    )?;
+    for (label, extremum) in [("  - Min: ", stats.min), ("  - Max: ", stats.max)] {
+        let txt = match extremum {
+            Some(Extremum { index, value }) => format!("{value:.4} at [{index}]"),
+            None => "-".to_owned(),
+        };
+        print_colored_quoted(out, Color::Yellow, label, txt)?;
+    }
+    // Quantiles are prefixed with '~' if they are approximations
+    let approx = if stats.quantiles_exact { "" } else { "~" };
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Median: ",
+        format!("{approx}{:.4}", stats.median),
+    )?;
+    for (p, value) in &stats.percentiles {
+        print_colored_quoted(
+            out,
+            Color::Yellow,
+            format!("  - p{p}: "),
+            format!("{approx}{value:.4}"),
+        )?;
+    }
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Skewness: ",
+        format!("{:.4}", stats.skewness),
+    )?;
+    print_colored_quoted(
+        out,
+        Color::Yellow,
+        "  - Kurtosis: ",
+        format!("{:.4}", stats.kurtosis),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use anyhow::bail;
+
+use super::{quantile::QuantileEstimator, Extremum, Statistics};
+use crate::config::misc::NanPolicy;
+
+/// Single-pass, numerically stable accumulator of [`Statistics`]
+///
+/// The central moments are updated incrementally with Welford's method, extended to the third and
+/// fourth moment (Terriberry, 2007), so the values never have to be kept in memory.
+#[derive(Debug, Clone)]
+pub struct StatsAccumulator {
+    /// Number of values pushed, including NaN and infinities
+    len: usize,
+    count: usize,
+    nan_count: usize,
+    pos_inf_count: usize,
+    neg_inf_count: usize,
+    nan_policy: NanPolicy,
+    sum: f64,
+    /// Exact sum of the values pushed as integers, an i128 holds the sum of 2^63 `u64::MAX`
+    integer_sum: Option<i128>,
+    mean: f64,
+    m2: f64,
+    m3: f64,
+    m4: f64,
+    min: Option<Extremum>,
+    max: Option<Extremum>,
+    /// Requested percentiles in the range 0-100
+    percentiles: Vec<f64>,
+    /// Estimates the median followed by the requested percentiles
+    quantiles: Option<QuantileEstimator>,
+}
+
+impl StatsAccumulator {
+    /// Accumulate the moments, extrema, median and `percentiles` (0-100), buffering at most
+    /// `memory_budget` bytes of values for exact quantiles
+    pub fn new(percentiles: &[f64], memory_budget: usize) -> Self {
+        let quantiles: Vec<f64> = std::iter::once(0.5)
+            .chain(percentiles.iter().map(|p| p / 100.0))
+            .collect();
+        Self {
+            percentiles: percentiles.to_vec(),
+            quantiles: Some(QuantileEstimator::new(&quantiles, memory_budget)),
+            ..Self::moments_only()
+        }
+    }
+
+    /// Accumulate only the moments and extrema, the median and percentiles will be NaN
+    pub fn moments_only() -> Self {
+        Self {
+            len: 0,
+            count: 0,
+            nan_count: 0,
+            pos_inf_count: 0,
+            neg_inf_count: 0,
+            nan_policy: NanPolicy::default(),
+            sum: 0.0,
+            integer_sum: None,
+            mean: 0.0,
+            m2: 0.0,
+            m3: 0.0,
+            m4: 0.0,
+            min: None,
+            max: None,
+            percentiles: vec![],
+            quantiles: None,
+        }
+    }
+
+    pub fn with_nan_policy(mut self, nan_policy: NanPolicy) -> Self {
+        self.nan_policy = nan_policy;
+        self
+    }
+
+    /// Add an integer value, which is also added to an exact integer sum
+    pub fn push_integer(&mut self, value: i128) -> anyhow::Result<()> {
+        self.integer_sum = Some(self.integer_sum.unwrap_or(0) + value);
+        self.push(value as f64)
+    }
+
+    /// Add a value, fails if the value is NaN or infinite and the policy is [`NanPolicy::Error`]
+    pub fn push(&mut self, value: f64) -> anyhow::Result<()> {
+        let index = self.len;
+        self.len += 1;
+
+        if !value.is_finite() {
+            if value.is_nan() {
+                self.nan_count += 1;
+            } else if value.is_sign_positive() {
+                self.pos_inf_count += 1;
+            } else {
+                self.neg_inf_count += 1;
+            }
+            match self.nan_policy {
+                NanPolicy::Skip => return Ok(()),
+                NanPolicy::Error => bail!("Encountered {value} at index {index}"),
+                NanPolicy::Propagate if value.is_nan() => {
+                    // NaN never compares less or greater, so the first NaN sticks as the extremum
+                    if !self.min.is_some_and(|m| m.value.is_nan()) {
+                        self.min = Some(Extremum { index, value });
+                        self.max = Some(Extremum { index, value });
+                    }
+                }
+                NanPolicy::Propagate => (),
+            }
+        }
+
+        if self.min.is_none_or(|m| value < m.value) {
+            self.min = Some(Extremum { index, value });
+        }
+        if self.max.is_none_or(|m| value > m.value) {
+            self.max = Some(Extremum { index, value });
+        }
+        if let Some(quantiles) = &mut self.quantiles {
+            quantiles.push(value);
+        }
+
+        let n1 = self.count as f64;
+        self.count += 1;
+        let n = self.count as f64;
+        let delta = value - self.mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * n1;
+
+        self.sum += value;
+        self.mean += delta_n;
+        self.m4 += term1 * delta_n2 * (n * n - 3.0 * n + 3.0) + 6.0 * delta_n2 * self.m2
+            - 4.0 * delta_n * self.m3;
+        self.m3 += term1 * delta_n * (n - 2.0) - 3.0 * delta_n * self.m2;
+        self.m2 += term1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Statistics {
+        let n = self.count as f64;
+        let propagate_nan = self.nan_policy == NanPolicy::Propagate && self.nan_count > 0;
+        let (estimates, quantiles_exact) = match &mut self.quantiles {
+            Some(_) if propagate_nan => (vec![f64::NAN; self.percentiles.len() + 1], true),
+            Some(q) => (q.estimates(), q.is_exact()),
+            None => (vec![], true),
+        };
+        let mut estimates = estimates.into_iter();
+
+        Statistics {
+            count: self.count,
+            nan_count: self.nan_count,
+            pos_inf_count: self.pos_inf_count,
+            neg_inf_count: self.neg_inf_count,
+            sum: self.integer_sum.map_or(self.sum, |s| s as f64),
+            integer_sum: self.integer_sum,
+            mean: match self.integer_sum {
+                _ if self.count == 0 => f64::NAN,
+                Some(s) => s as f64 / n,
+                None => self.mean,
+            },
+            std_dev: (self.m2 / n).sqrt(),
+            min: self.min,
+            max: self.max,
+            median: estimates.next().unwrap_or(f64::NAN),
+            percentiles: self.percentiles.into_iter().zip(estimates).collect(),
+            quantiles_exact,
+            skewness: n.sqrt() * self.m3 / self.m2.powf(1.5),
+            kurtosis: n * self.m4 / (self.m2 * self.m2) - 3.0,
+        }
+    }
+}
+
+/// Values that can be added to a [`StatsAccumulator`]
+pub trait Accumulate {
+    fn accumulate(self, acc: &mut StatsAccumulator) -> anyhow::Result<()>;
+}
+
+impl Accumulate for f64 {
+    fn accumulate(self, acc: &mut StatsAccumulator) -> anyhow::Result<()> {
+        acc.push(self)
+    }
+}
+
+impl Accumulate for i128 {
+    fn accumulate(self, acc: &mut StatsAccumulator) -> anyhow::Result<()> {
+        acc.push_integer(self)
+    }
+}
@@ -0,0 +1,183 @@
+use std::mem::size_of;
+
+/// Estimates quantiles of a stream of values
+///
+/// Values are buffered to compute exact quantiles until the buffer exceeds the memory budget.
+/// From then on each quantile is approximated with the P² algorithm (Jain & Chlamtac, 1985)
+/// which uses five markers per quantile, so memory stays constant regardless of the stream length.
+#[derive(Debug, Clone)]
+pub struct QuantileEstimator {
+    /// Requested quantiles in the range 0-1
+    quantiles: Vec<f64>,
+    max_values: usize,
+    values: Vec<f64>,
+    markers: Option<Vec<P2Quantile>>,
+}
+
+impl QuantileEstimator {
+    pub fn new(quantiles: &[f64], memory_budget: usize) -> Self {
+        Self {
+            quantiles: quantiles.to_vec(),
+            max_values: memory_budget / size_of::<f64>(),
+            values: vec![],
+            markers: None,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if let Some(markers) = &mut self.markers {
+            markers.iter_mut().for_each(|m| m.push(value));
+            return;
+        }
+
+        self.values.push(value);
+        if self.values.len() > self.max_values {
+            log::debug!(
+                "Exceeded {} buffered values, switching to approximate quantiles",
+                self.max_values
+            );
+            let values = std::mem::take(&mut self.values);
+            self.markers = Some(
+                self.quantiles
+                    .iter()
+                    .map(|&q| {
+                        let mut m = P2Quantile::new(q);
+                        values.iter().for_each(|&v| m.push(v));
+                        m
+                    })
+                    .collect(),
+            );
+        }
+    }
+
+    /// Whether the estimates are exact, which is the case as long as all values fit in the buffer
+    pub fn is_exact(&self) -> bool {
+        self.markers.is_none()
+    }
+
+    /// The estimate of each requested quantile, in the order they were requested
+    pub fn estimates(&mut self) -> Vec<f64> {
+        match &self.markers {
+            Some(markers) => markers.iter().map(P2Quantile::estimate).collect(),
+            None => {
+                self.values.sort_unstable_by(f64::total_cmp);
+                self.quantiles
+                    .iter()
+                    .map(|&q| quantile_of_sorted(&self.values, q))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Quantile `q` (0-1) of sorted values, linearly interpolating between the closest ranks
+pub fn quantile_of_sorted(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = q * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+/// P² estimator of a single quantile
+#[derive(Debug, Clone)]
+struct P2Quantile {
+    q: f64,
+    count: usize,
+    /// Marker heights
+    heights: [f64; 5],
+    /// Actual marker positions
+    positions: [f64; 5],
+    /// Desired marker positions
+    desired: [f64; 5],
+    /// Increments of the desired marker positions
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    fn new(q: f64) -> Self {
+        Self {
+            q,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * q, 1.0 + 4.0 * q, 3.0 + 2.0 * q, 5.0],
+            increments: [0.0, q / 2.0, q, (1.0 + q) / 2.0, 1.0],
+        }
+    }
+
+    fn push(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_unstable_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        let h = &mut self.heights;
+        // Find the cell containing x, extending the extreme markers if needed
+        let k = if x < h[0] {
+            h[0] = x;
+            0
+        } else if x >= h[4] {
+            h[4] = x;
+            3
+        } else {
+            (1..5).find(|&i| x < h[i]).unwrap_or(4) - 1
+        };
+
+        self.positions[k + 1..].iter_mut().for_each(|n| *n += 1.0);
+        for (d, inc) in self.desired.iter_mut().zip(self.increments) {
+            *d += inc;
+        }
+
+        // Adjust the heights of the middle markers if they are off their desired positions
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            let n = &self.positions;
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let parabolic = self.parabolic(i, d);
+                self.heights[i] =
+                    if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                        parabolic
+                    } else {
+                        self.linear(i, d)
+                    };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (h, n) = (&self.heights, &self.positions);
+        h[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (h[i + 1] - h[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (h[i] - h[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let (h, n) = (&self.heights, &self.positions);
+        let j = if d > 0.0 { i + 1 } else { i - 1 };
+        h[i] + d * (h[j] - h[i]) / (n[j] - n[i])
+    }
+
+    fn estimate(&self) -> f64 {
+        if self.count < 5 {
+            let mut values = self.heights[..self.count].to_vec();
+            values.sort_unstable_by(f64::total_cmp);
+            return quantile_of_sorted(&values, self.q);
+        }
+        // The extreme markers track the exact minimum and maximum
+        match self.q {
+            q if q <= 0.0 => self.heights[0],
+            q if q >= 1.0 => self.heights[4],
+            _ => self.heights[2],
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::io::Write;
+
+use hdf5::Dataset;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use termcolor::{Color, StandardStream};
+
+use super::Statistics;
+use crate::{my_hdf5::util::NativePrimitiveType, util::print_table};
+
+/// Statistics of a dataset, or of one lane of a dataset, with the stable schema used for
+/// machine-readable output
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsRecord {
+    pub dataset: String,
+    pub dtype: &'static str,
+    pub shape: Vec<usize>,
+    /// Index of the lane for per-lane statistics, otherwise `null`
+    pub lane: Option<usize>,
+    #[serde(flatten)]
+    pub statistics: Statistics,
+}
+
+impl StatsRecord {
+    /// `dtype` and `shape` are those of the values the statistics are computed from, which
+    /// differ from those of the dataset if it is unpacked or resampled
+    pub fn new(
+        dataset: &Dataset,
+        dtype: NativePrimitiveType,
+        shape: Vec<usize>,
+        lane: Option<usize>,
+        statistics: Statistics,
+    ) -> Self {
+        Self {
+            dataset: dataset.name(),
+            dtype: dtype.name(),
+            shape,
+            lane,
+            statistics,
+        }
+    }
+
+    /// Column names of [`StatsRecord::csv_row`]
+    pub fn csv_header(percentiles: &[f64]) -> Vec<String> {
+        let mut header: Vec<String> = [
+            "dataset",
+            "dtype",
+            "shape",
+            "lane",
+            "count",
+            "nan_count",
+            "pos_inf_count",
+            "neg_inf_count",
+            "sum",
+            "mean",
+            "std_dev",
+            "min",
+            "argmin",
+            "max",
+            "argmax",
+            "median",
+        ]
+        .map(String::from)
+        .into();
+        header.extend(percentiles.iter().map(|p| format!("p{p}")));
+        header.extend(["quantiles_exact", "skewness", "kurtosis"].map(String::from));
+        header
+    }
+
+    /// The fields as CSV cells, the shape is written as e.g. `1000x3` and absent values are empty
+    pub fn csv_row(&self) -> Vec<String> {
+        fn opt<T: ToString>(v: Option<T>) -> String {
+            v.map(|v| v.to_string()).unwrap_or_default()
+        }
+        let stats = &self.statistics;
+        let shape: Vec<String> = self.shape.iter().map(usize::to_string).collect();
+        let mut row = vec![
+            self.dataset.clone(),
+            self.dtype.to_owned(),
+            shape.join("x"),
+            opt(self.lane),
+            stats.count.to_string(),
+            stats.nan_count.to_string(),
+            stats.pos_inf_count.to_string(),
+            stats.neg_inf_count.to_string(),
+            stats
+                .integer_sum
+                .map_or(stats.sum.to_string(), |sum| sum.to_string()),
+            stats.mean.to_string(),
+            stats.std_dev.to_string(),
+            opt(stats.min.map(|m| m.value)),
+            opt(stats.min.map(|m| m.index)),
+            opt(stats.max.map(|m| m.value)),
+            opt(stats.max.map(|m| m.index)),
+            stats.median.to_string(),
+        ];
+        row.extend(stats.percentiles.iter().map(|(_, v)| v.to_string()));
+        row.push(stats.quantiles_exact.to_string());
+        row.push(stats.skewness.to_string());
+        row.push(stats.kurtosis.to_string());
+        row
+    }
+}
+
+/// Serialized as a flat map with the fields of the CSV columns, with the extrema split into
+/// value and index, e.g. `min` and `argmin`, and an entry per percentile, e.g. `"p99": 1.2`
+impl Serialize for Statistics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("count", &self.count)?;
+        map.serialize_entry("nan_count", &self.nan_count)?;
+        map.serialize_entry("pos_inf_count", &self.pos_inf_count)?;
+        map.serialize_entry("neg_inf_count", &self.neg_inf_count)?;
+        match self.integer_sum {
+            Some(sum) => map.serialize_entry("sum", &sum)?,
+            None => map.serialize_entry("sum", &self.sum)?,
+        }
+        map.serialize_entry("mean", &self.mean)?;
+        map.serialize_entry("std_dev", &self.std_dev)?;
+        map.serialize_entry("min", &self.min.map(|m| m.value))?;
+        map.serialize_entry("argmin", &self.min.map(|m| m.index))?;
+        map.serialize_entry("max", &self.max.map(|m| m.value))?;
+        map.serialize_entry("argmax", &self.max.map(|m| m.index))?;
+        map.serialize_entry("median", &self.median)?;
+        for (p, value) in &self.percentiles {
+            map.serialize_entry(&format!("p{p}"), value)?;
+        }
+        map.serialize_entry("quantiles_exact", &self.quantiles_exact)?;
+        map.serialize_entry("skewness", &self.skewness)?;
+        map.serialize_entry("kurtosis", &self.kurtosis)?;
+        map.end()
+    }
+}
+
+/// Write the records as a JSON array, NaN values are written as `null`
+pub fn write_json<W: Write>(out: &mut W, records: &[StatsRecord]) -> anyhow::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, records)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+pub fn write_csv<W: Write>(
+    out: &mut W,
+    records: &[StatsRecord],
+    percentiles: &[f64],
+) -> anyhow::Result<()> {
+    write_csv_line(out, &StatsRecord::csv_header(percentiles))?;
+    for record in records {
+        write_csv_line(out, &record.csv_row())?;
+    }
+    Ok(())
+}
+
+fn write_csv_line<W: Write>(out: &mut W, cells: &[String]) -> anyhow::Result<()> {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_owned()
+            }
+        })
+        .collect();
+    writeln!(out, "{}", cells.join(","))?;
+    Ok(())
+}
+
+/// Print the records as a table with a row per record, the lane column is included if any of
+/// the records are per-lane statistics
+pub fn print_table_of_records(
+    out: &mut StandardStream,
+    records: &[StatsRecord],
+    percentiles: &[f64],
+) -> anyhow::Result<()> {
+    let lanes = records.iter().any(|r| r.lane.is_some());
+    let mut header = vec!["dataset".to_owned()];
+    if lanes {
+        header.push("lane".to_owned());
+    }
+    header.extend(Statistics::table_header(percentiles));
+
+    let rows: Vec<Vec<String>> = records
+        .iter()
+        .map(|record| {
+            let mut row = vec![record.dataset.clone()];
+            if lanes {
+                row.push(record.lane.map(|l| l.to_string()).unwrap_or_default());
+            }
+            row.extend(record.statistics.table_row());
+            row
+        })
+        .collect();
+    print_table(out, Color::Cyan, &header, &rows)
+}
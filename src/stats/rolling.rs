@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+
+/// Statistics over a trailing window of samples
+///
+/// Each series has the same length as the input, values are `None` until the first window is
+/// full and for windows without any finite values. NaN and infinite values are left out of
+/// the windows they are part of.
+#[derive(Debug, Clone, Default)]
+pub struct Rolling {
+    pub window: usize,
+    pub mean: Vec<Option<f64>>,
+    pub std_dev: Vec<Option<f64>>,
+    pub min: Vec<Option<f64>>,
+    pub max: Vec<Option<f64>>,
+}
+
+impl Rolling {
+    /// Compute the rolling statistics in O(n), the mean and σ are updated as values enter and
+    /// leave the window, and the extrema are tracked with monotonic queues
+    pub fn new(values: &[f64], window: usize) -> Self {
+        let len = values.len();
+        let mut rolling = Self {
+            window,
+            mean: Vec::with_capacity(len),
+            std_dev: Vec::with_capacity(len),
+            min: Vec::with_capacity(len),
+            max: Vec::with_capacity(len),
+        };
+
+        let mut moments = WindowMoments::default();
+        // Indices of candidates for the extrema of the window, their values are monotonic
+        let mut min_queue: VecDeque<usize> = VecDeque::new();
+        let mut max_queue: VecDeque<usize> = VecDeque::new();
+
+        for (i, &x) in values.iter().enumerate() {
+            if x.is_finite() {
+                moments.add(x);
+                while min_queue.back().is_some_and(|&j| values[j] >= x) {
+                    min_queue.pop_back();
+                }
+                min_queue.push_back(i);
+                while max_queue.back().is_some_and(|&j| values[j] <= x) {
+                    max_queue.pop_back();
+                }
+                max_queue.push_back(i);
+            }
+
+            if i >= window {
+                let leaving = i - window;
+                if values[leaving].is_finite() {
+                    moments.remove(values[leaving]);
+                }
+                if min_queue.front() == Some(&leaving) {
+                    min_queue.pop_front();
+                }
+                if max_queue.front() == Some(&leaving) {
+                    max_queue.pop_front();
+                }
+            }
+
+            let full = i + 1 >= window && moments.count > 0;
+            rolling.mean.push(full.then_some(moments.mean));
+            rolling.std_dev.push(full.then(|| moments.std_dev()));
+            rolling
+                .min
+                .push(min_queue.front().filter(|_| full).map(|&j| values[j]));
+            rolling
+                .max
+                .push(max_queue.front().filter(|_| full).map(|&j| values[j]));
+        }
+
+        rolling
+    }
+}
+
+/// Mean and sum of squared deviations of the values in a window, with Welford updates for
+/// adding and removing values
+#[derive(Debug, Default)]
+struct WindowMoments {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl WindowMoments {
+    fn add(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn remove(&mut self, x: f64) {
+        if self.count <= 1 {
+            *self = Self::default();
+            return;
+        }
+        self.count -= 1;
+        let delta = x - self.mean;
+        self.mean -= delta / self.count as f64;
+        self.m2 -= delta * (x - self.mean);
+    }
+
+    /// Population standard deviation, the sum of squares can drift slightly below zero
+    fn std_dev(&self) -> f64 {
+        (self.m2.max(0.0) / self.count as f64).sqrt()
+    }
+}
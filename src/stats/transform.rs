@@ -0,0 +1,163 @@
+use std::ops::{Add, Sub};
+
+use anyhow::{bail, Context};
+use hdf5::Dataset;
+use num_traits::{ToPrimitive, Zero};
+
+use super::accumulator::{Accumulate, StatsAccumulator};
+use crate::{config::misc::Transform, my_hdf5::chunked::elements};
+
+/// A transformed sample, differences and sums of integers are kept exact
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transformed<W> {
+    Exact(W),
+    Float(f64),
+}
+
+impl<W: ToPrimitive> Transformed<W> {
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Transformed::Exact(x) => x.to_f64().unwrap_or(f64::NAN),
+            Transformed::Float(x) => *x,
+        }
+    }
+}
+
+impl<W: Accumulate> Accumulate for Transformed<W> {
+    fn accumulate(self, acc: &mut StatsAccumulator) -> anyhow::Result<()> {
+        match self {
+            Transformed::Exact(x) => x.accumulate(acc),
+            Transformed::Float(x) => acc.push(x),
+        }
+    }
+}
+
+/// Streaming state of a [`Transform`]
+///
+/// NaN and infinite samples are transformed to NaN and otherwise skipped, so a difference after a
+/// gap is taken to the last finite sample, and the sums continue after the gap.
+#[derive(Debug, Clone)]
+pub struct Transformer<W> {
+    transform: Transform,
+    /// Position on the x-axis and value of the last finite sample
+    prev: Option<(f64, W)>,
+    sum: W,
+    integral: f64,
+}
+
+impl<W> Transformer<W>
+where
+    W: Copy + Zero + Add<Output = W> + Sub<Output = W> + ToPrimitive,
+{
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            transform,
+            prev: None,
+            sum: W::zero(),
+            integral: 0.0,
+        }
+    }
+
+    /// Transform the next sample at position `t` on the x-axis, the differences have no value
+    /// for the first sample
+    pub fn push(&mut self, t: f64, x: W) -> Option<Transformed<W>> {
+        let is_difference = matches!(self.transform, Transform::Diff | Transform::Derivative);
+        let value = x.to_f64().unwrap_or(f64::NAN);
+        if !value.is_finite() {
+            if is_difference && self.prev.is_none() {
+                return None;
+            }
+            return Some(Transformed::Float(f64::NAN));
+        }
+
+        let prev = self.prev.replace((t, x));
+        match self.transform {
+            Transform::Diff => prev.map(|(_, p)| Transformed::Exact(x - p)),
+            Transform::Derivative => prev.map(|(pt, p)| {
+                let dx = (x - p).to_f64().unwrap_or(f64::NAN);
+                Transformed::Float(dx / (t - pt))
+            }),
+            Transform::Cumsum => {
+                self.sum = self.sum + x;
+                Some(Transformed::Exact(self.sum))
+            }
+            Transform::Integral => {
+                if let Some((pt, p)) = prev {
+                    let p = p.to_f64().unwrap_or(f64::NAN);
+                    self.integral += (t - pt) * (p + value) / 2.0;
+                }
+                Some(Transformed::Float(self.integral))
+            }
+        }
+    }
+}
+
+/// Wrap `f` to visit the transformed samples along with their position on the x-axis
+///
+/// The samples must be visited in order. The position of each sample is read from the 1-D
+/// `x_axis` dataset, which must have one value per sample of the series of length `len`, in
+/// blocks of at most `memory_budget` bytes, or is the index of the sample without one. Without a
+/// transform the samples are passed on unchanged.
+pub fn transformed<'a, W, F>(
+    transform: Option<Transform>,
+    x_axis: Option<&'a Dataset>,
+    len: usize,
+    memory_budget: usize,
+    f: F,
+) -> anyhow::Result<impl FnMut(usize, W) -> anyhow::Result<()> + 'a>
+where
+    W: Copy + Zero + Add<Output = W> + Sub<Output = W> + ToPrimitive + 'a,
+    F: FnMut(usize, f64, Transformed<W>) -> anyhow::Result<()> + 'a,
+{
+    if let Some(x_axis) = x_axis {
+        if x_axis.ndim() != 1 {
+            bail!(
+                "The x-axis dataset {} must be 1-D, got a {}-D dataset",
+                x_axis.name(),
+                x_axis.ndim()
+            );
+        }
+        let positions = x_axis.shape()[0];
+        if positions != len {
+            bail!(
+                "The x-axis dataset {} has {positions} values but the series has {len} samples",
+                x_axis.name()
+            );
+        }
+    }
+    let mut positions = x_axis.map(|x_axis| elements::<f64>(x_axis, memory_budget));
+    let mut f = positioned(transform, f);
+
+    Ok(move |i, sample| {
+        let t = match &mut positions {
+            Some(positions) => positions
+                .next()
+                .context("The x-axis dataset has fewer values than the series")??,
+            None => i as f64,
+        };
+        f(i, t, sample)
+    })
+}
+
+/// Like [`transformed`], for samples visited along with their position, e.g. after resampling
+pub fn positioned<W, F>(
+    transform: Option<Transform>,
+    mut f: F,
+) -> impl FnMut(usize, f64, W) -> anyhow::Result<()>
+where
+    W: Copy + Zero + Add<Output = W> + Sub<Output = W> + ToPrimitive,
+    F: FnMut(usize, f64, Transformed<W>) -> anyhow::Result<()>,
+{
+    let mut transformer = transform.map(Transformer::new);
+
+    move |i, t, sample| {
+        let y = match &mut transformer {
+            Some(transformer) => match transformer.push(t, sample) {
+                Some(y) => y,
+                None => return Ok(()),
+            },
+            None => Transformed::Exact(sample),
+        };
+        f(i, t, y)
+    }
+}
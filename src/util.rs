@@ -35,3 +35,89 @@ where
     out.reset()?;
     Ok(())
 }
+
+/// Print rows of cells as a table with right-aligned columns, the header and the first column
+/// are printed in `color`
+pub fn print_table<H>(
+    out: &mut StandardStream,
+    color: termcolor::Color,
+    header: &[H],
+    rows: &[Vec<String>],
+) -> anyhow::Result<()>
+where
+    H: AsRef<str>,
+{
+    let mut widths: Vec<usize> = header.iter().map(|h| h.as_ref().chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    out.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+    for (i, (h, width)) in header.iter().zip(&widths).enumerate() {
+        let sep = if i == 0 { "" } else { "  " };
+        write!(out, "{sep}{:>width$}", h.as_ref())?;
+    }
+    writeln!(out)?;
+    out.reset()?;
+
+    for row in rows {
+        for (i, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if i == 0 {
+                out.set_color(ColorSpec::new().set_fg(Some(color)))?;
+                write!(out, "{cell:>width$}")?;
+                out.reset()?;
+            } else {
+                write!(out, "  {cell:>width$}")?;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Human readable size in binary units, e.g. `1.5 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Whether `path` matches the glob `pattern`, where `*` matches within one path component,
+/// `**` matches across components and `?` matches one character
+///
+/// Patterns without a leading `/` match the end of the path, e.g. `adc/*_raw` matches
+/// `/logger/adc/ch0_raw`.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    if pattern.first() == Some(&'/') {
+        return glob_match_chars(&pattern, &path);
+    }
+    (0..path.len())
+        .filter(|&i| path[i] == '/')
+        .any(|i| glob_match_chars(&pattern, &path[i + 1..]))
+}
+
+fn glob_match_chars(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|i| glob_match_chars(rest, &path[i..])),
+        ['*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != '/')
+            .any(|i| glob_match_chars(rest, &path[i..])),
+        ['?', rest @ ..] => {
+            matches!(path, [c, ..] if *c != '/') && glob_match_chars(rest, &path[1..])
+        }
+        [c, rest @ ..] => path.first() == Some(c) && glob_match_chars(rest, &path[1..]),
+    }
+}